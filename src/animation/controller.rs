@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Context, Result};
+use hecs::World;
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path, sync::Arc};
+
+use super::{AnimatedSprite, PlaybackMode};
+use crate::texture_atlas::{Animation, Region};
+
+/*
+   An animation graph is a set of states, each playing a clip from a tagged animation region, and the transitions between them.
+
+   Every update, the transitions out of the current state are checked in order, and the first one whose conditions all hold
+   (and whose exit time has passed) is taken. Transitions from "*" can be taken from any state.
+
+   Parameters are all f32s, with bools stored as 0.0 and 1.0, and are set by whatever system knows about them.
+*/
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Condition {
+    pub param: String,
+    pub comparison: Comparison,
+    pub value: f32,
+}
+
+impl Condition {
+    // parameters that were never set count as 0.0
+    fn holds(&self, params: &HashMap<String, f32>) -> bool {
+        let param = params.get(&self.param).copied().unwrap_or(0.0);
+        match self.comparison {
+            Comparison::Greater => param > self.value,
+            Comparison::GreaterOrEqual => param >= self.value,
+            Comparison::Less => param < self.value,
+            Comparison::LessOrEqual => param <= self.value,
+            Comparison::Equal => param == self.value,
+            Comparison::NotEqual => param != self.value,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct StateDefinition {
+    // the tag to play, or the whole animation if left out
+    #[serde(default)]
+    pub clip: Option<String>,
+    pub mode: PlaybackMode,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    // multiplies the playback speed by a parameter, so walk cycles can keep up with the entity
+    #[serde(default)]
+    pub speed_param: Option<String>,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    // how much of the current clip has to have played, from 0 to 1, before this can be taken
+    #[serde(default)]
+    pub exit_time: Option<f32>,
+    // start the new clip at the same normalized time the old one was at, for clips that line up like walk and run
+    #[serde(default)]
+    pub sync_frames: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimationGraph {
+    pub initial: String,
+    // in seconds, for animations whose frames don't have durations in the atlas
+    #[serde(default = "default_frame_duration")]
+    pub default_duration: f32,
+    pub states: HashMap<String, StateDefinition>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+fn default_frame_duration() -> f32 {
+    0.1
+}
+
+#[allow(dead_code)]
+impl AnimationGraph {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AnimationGraph> {
+        let graph: AnimationGraph = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if !graph.states.contains_key(&graph.initial) {
+            return Err(anyhow!("initial state {} doesn't exist", graph.initial));
+        }
+        for transition in graph.transitions.iter() {
+            if transition.from != "*" && !graph.states.contains_key(&transition.from) {
+                return Err(anyhow!("transition from unknown state {}", transition.from));
+            }
+            if !graph.states.contains_key(&transition.to) {
+                return Err(anyhow!("transition to unknown state {}", transition.to));
+            }
+        }
+        Ok(graph)
+    }
+}
+
+pub struct AnimationController {
+    graph: Arc<AnimationGraph>,
+    clips: HashMap<String, Animation>,
+    params: HashMap<String, f32>,
+    state: String,
+}
+
+#[allow(dead_code)]
+impl AnimationController {
+    // animation has to be an animation region with a tag for every clip the graph uses.
+    // returns the sprite for the initial state alongside the controller, and both should be added to the same entity
+    pub fn new(graph: Arc<AnimationGraph>, animation: &Region) -> Result<(AnimationController, AnimatedSprite)> {
+        let Region::Animation(whole) = animation else {
+            return Err(anyhow!("animation controllers need an animation region"));
+        };
+        let mut clips = HashMap::new();
+        for (name, state) in graph.states.iter() {
+            let clip = match &state.clip {
+                Some(tag) => whole
+                    .clip(tag)
+                    .ok_or(anyhow!("state {name} plays {tag}, which isn't tagged in the animation"))?,
+                None => whole.clone(),
+            };
+            // made once here so switching to the state later can't fail
+            AnimatedSprite::from_animation(clip.clone(), graph.default_duration, state.mode)
+                .with_context(|| format!("state {name} can't be played"))?;
+            clips.insert(name.clone(), clip);
+        }
+        let controller = AnimationController {
+            state: graph.initial.clone(),
+            graph,
+            clips,
+            params: HashMap::new(),
+        };
+        let sprite = controller.sprite_for(&controller.state);
+        Ok((controller, sprite))
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+    pub fn set_float(&mut self, param: &str, value: f32) {
+        self.params.insert(param.to_string(), value);
+    }
+    pub fn set_bool(&mut self, param: &str, value: bool) {
+        self.set_float(param, if value { 1.0 } else { 0.0 });
+    }
+    pub fn get(&self, param: &str) -> f32 {
+        self.params.get(param).copied().unwrap_or(0.0)
+    }
+
+    fn sprite_for(&self, state: &str) -> AnimatedSprite {
+        let definition = &self.graph.states[state];
+        AnimatedSprite::from_animation(self.clips[state].clone(), self.graph.default_duration, definition.mode)
+            .expect("every clip was played once when the controller was made")
+    }
+
+    // takes at most one transition, returns true if the state changed
+    pub fn update(&mut self, sprite: &mut AnimatedSprite) -> bool {
+        let transition = self.graph.transitions.iter().find(|transition| {
+            (transition.from == self.state || transition.from == "*" && transition.to != self.state)
+                && transition
+                    .exit_time
+                    .map_or(true, |exit_time| sprite.normalized_time() >= exit_time || sprite.is_finished())
+                && transition.conditions.iter().all(|condition| condition.holds(&self.params))
+        });
+        let changed = if let Some(transition) = transition {
+            let mut next = self.sprite_for(&transition.to);
+            if transition.sync_frames {
+                next.set_normalized_time(sprite.normalized_time());
+            }
+            *sprite = next;
+            self.state = transition.to.clone();
+            true
+        } else {
+            false
+        };
+        let definition = &self.graph.states[&self.state];
+        sprite.speed = definition.speed
+            * definition
+                .speed_param
+                .as_ref()
+                .map_or(1.0, |param| self.get(param));
+        changed
+    }
+}
+
+pub fn update_controllers(world: &mut World) {
+    for (_, (controller, sprite)) in world.query_mut::<(&mut AnimationController, &mut AnimatedSprite)>() {
+        controller.update(sprite);
+    }
+}
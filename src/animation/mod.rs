@@ -0,0 +1,200 @@
+use anyhow::{anyhow, ensure, Result};
+use hecs::{Entity, World};
+use serde::Deserialize;
+
+use crate::{gfx::pass::deferred_pass::SpriteTransform, renderer::RendererState, texture_atlas::{Animation, Region}};
+
+pub mod controller;
+
+#[allow(dead_code)]
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackMode {
+    Loop,
+    // plays forwards then backwards without repeating the end frames, 0 1 2 1 0 1 2 ...
+    PingPong,
+    // stops on the last frame
+    Once,
+}
+
+// sent once when a Once animation reaches the end of its last frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationFinished(pub Entity);
+
+#[derive(Clone, Debug)]
+pub struct AnimatedSprite {
+    frames: Vec<Region>,
+    // in seconds, one per frame
+    durations: Vec<f32>,
+    pub mode: PlaybackMode,
+    // multiplies the elapsed time, so 2.0 plays twice as fast
+    pub speed: f32,
+    frame: usize,
+    elapsed: f32,
+    reverse: bool,
+    finished: bool,
+}
+
+#[allow(dead_code)]
+impl AnimatedSprite {
+    // fails unless animation is a Region::Animation with at least one frame, whose frames are all Single or NormalPair regions.
+    // default_duration is only used if the atlas doesn't give the frames durations
+    pub fn new(animation: &Region, default_duration: f32, mode: PlaybackMode) -> Result<AnimatedSprite> {
+        let Region::Animation(animation) = animation else {
+            return Err(anyhow!("AnimatedSprite needs an animation region, was given {animation:?}"));
+        };
+        AnimatedSprite::from_animation(animation.clone(), default_duration, mode)
+    }
+    // plays only the frames tagged with tag
+    pub fn from_clip(animation: &Region, tag: &str, default_duration: f32, mode: PlaybackMode) -> Result<AnimatedSprite> {
+        let clip = animation.get_clip(tag).ok_or(anyhow!("{tag} isn't tagged in the animation"))?;
+        AnimatedSprite::from_animation(clip, default_duration, mode)
+    }
+    pub fn from_animation(animation: Animation, default_duration: f32, mode: PlaybackMode) -> Result<AnimatedSprite> {
+        let mut sprite = AnimatedSprite::from_frames(animation.frames, default_duration, mode)?;
+        if animation.durations.len() == sprite.frames.len() {
+            sprite.durations = animation.durations;
+        }
+        Ok(sprite)
+    }
+    pub fn from_frames(frames: Vec<Region>, frame_duration: f32, mode: PlaybackMode) -> Result<AnimatedSprite> {
+        ensure!(!frames.is_empty(), "animations need at least one frame");
+        for frame in frames.iter() {
+            ensure!(
+                matches!(frame, Region::Single(_) | Region::NormalPair(_)),
+                "animation frames must be Single or NormalPair regions: {frame:?}"
+            );
+        }
+        Ok(AnimatedSprite {
+            durations: vec![frame_duration; frames.len()],
+            frames,
+            mode,
+            speed: 1.0,
+            frame: 0,
+            elapsed: 0.0,
+            reverse: false,
+            finished: false,
+        })
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn current_frame(&self) -> &Region {
+        &self.frames[self.frame]
+    }
+    pub fn frame_duration(&self, frame: usize) -> f32 {
+        self.durations[frame]
+    }
+    pub fn set_frame_durations(&mut self, duration: f32) {
+        self.durations.fill(duration);
+    }
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.reverse = false;
+        self.finished = false;
+    }
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame = frame.min(self.frames.len() - 1);
+        self.elapsed = 0.0;
+    }
+    // how far through the frames the sprite is, from 0 at the start of the first frame to 1 at the end of the last
+    pub fn normalized_time(&self) -> f32 {
+        let total: f32 = self.durations.iter().sum();
+        if total <= 0.0 {
+            return self.frame as f32 / self.frames.len() as f32;
+        }
+        let before: f32 = self.durations[..self.frame].iter().sum();
+        ((before + self.elapsed) / total).min(1.0)
+    }
+    pub fn set_normalized_time(&mut self, time: f32) {
+        let total: f32 = self.durations.iter().sum();
+        let mut remaining = time.clamp(0.0, 1.0) * total;
+        for (frame, &duration) in self.durations.iter().enumerate() {
+            if remaining < duration || frame == self.frames.len() - 1 {
+                self.frame = frame;
+                self.elapsed = remaining.min(duration);
+                return;
+            }
+            remaining -= duration;
+        }
+    }
+
+    // dt is in seconds, returns true on the update that the animation finishes
+    pub fn advance(&mut self, dt: f32) -> bool {
+        if self.finished {
+            return false;
+        }
+        self.elapsed += dt * self.speed;
+        // frames with no duration are skipped over, unless every frame has none
+        if self.durations.iter().all(|&duration| duration <= 0.0) {
+            return false;
+        }
+        while self.elapsed >= self.durations[self.frame] {
+            self.elapsed -= self.durations[self.frame];
+            if self.step() {
+                self.finished = true;
+                self.elapsed = 0.0;
+                return true;
+            }
+        }
+        false
+    }
+
+    // moves to the next frame, returning true if there wasn't one
+    fn step(&mut self) -> bool {
+        let last = self.frames.len() - 1;
+        match self.mode {
+            PlaybackMode::Loop => {
+                self.frame = if self.frame == last { 0 } else { self.frame + 1 };
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return false;
+                }
+                if self.reverse && self.frame == 0 || !self.reverse && self.frame == last {
+                    self.reverse = !self.reverse;
+                }
+                if self.reverse {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+            PlaybackMode::Once => {
+                if self.frame == last {
+                    return true;
+                }
+                self.frame += 1;
+            }
+        }
+        false
+    }
+
+    pub fn draw(&self, renderer: &mut RendererState, position: (f32, f32, f32)) -> Result<()> {
+        self.draw_transformed(renderer, position, SpriteTransform::default())
+    }
+    pub fn draw_transformed(&self, renderer: &mut RendererState, position: (f32, f32, f32), transform: SpriteTransform) -> Result<()> {
+        match self.current_frame() {
+            Region::Single(region) => renderer.draw_sprite_transformed(position, region.clone(), transform),
+            Region::NormalPair(region) => renderer.draw_deferred_sprite_transformed(position, region.clone(), transform),
+            _ => unreachable!("frames are checked when the sprite is created"),
+        }
+    }
+}
+
+pub fn update_animations(world: &mut World, dt: f32) -> Vec<AnimationFinished> {
+    let mut finished = Vec::new();
+    for (entity, sprite) in world.query_mut::<&mut AnimatedSprite>() {
+        if sprite.advance(dt) {
+            finished.push(AnimationFinished(entity));
+        }
+    }
+    finished
+}
@@ -1,4 +1,5 @@
 use crate::{
+    text::BitmapFont,
     texture::{Texture, TextureCreator},
     texture_atlas::TextureAtlas,
 };
@@ -38,6 +39,7 @@ where
 }
 
 pub type TextureAtlasStorage = AssetStorage<String, TextureAtlas>;
+pub type FontStorage = AssetStorage<String, BitmapFont>;
 
 pub trait AssetLoader<'a, R> {
     type Args: ?Sized;
@@ -72,3 +74,11 @@ impl<'a> AssetLoader<'a, TextureAtlas> for TextureCreator<'a> {
         }
     }
 }
+
+impl<'a> AssetLoader<'a, BitmapFont> for TextureCreator<'a> {
+    type Args = str;
+
+    fn load(&self, data: &Self::Args) -> Result<BitmapFont> {
+        BitmapFont::load_fnt(ASSETS_LOCATION.to_owned() + "/fonts/" + data + ".fnt", self)
+    }
+}
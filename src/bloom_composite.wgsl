@@ -0,0 +1,41 @@
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+}
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+
+struct Bloom {
+    // threshold, intensity
+    params: vec4f,
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var s_scene: sampler;
+@group(1) @binding(0)
+var t_bloom: texture_2d<f32>;
+@group(1) @binding(1)
+var s_bloom: sampler;
+@group(2) @binding(0)
+var<uniform> bloom: Bloom;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let scene = textureSample(t_scene, s_scene, in.tex_coords);
+    let glow = textureSample(t_bloom, s_bloom, in.tex_coords).rgb;
+    return vec4f(scene.rgb + glow * bloom.params.y, scene.a);
+}
@@ -0,0 +1,38 @@
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+}
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+// a box filter over the four input pixels under this one, plus a wider ring to keep the blur round
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(t_input));
+    var inner = textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-0.5, -0.5)).rgb;
+    inner += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.5, -0.5)).rgb;
+    inner += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-0.5, 0.5)).rgb;
+    inner += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.5, 0.5)).rgb;
+    var outer = textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-1.5, 0.0)).rgb;
+    outer += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(1.5, 0.0)).rgb;
+    outer += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.0, -1.5)).rgb;
+    outer += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.0, 1.5)).rgb;
+    return vec4f(inner * 0.125 + outer * 0.125, 1.0);
+}
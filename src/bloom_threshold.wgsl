@@ -0,0 +1,50 @@
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+}
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+
+struct Bloom {
+    // threshold, intensity
+    params: vec4f,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(1) @binding(0)
+var<uniform> bloom: Bloom;
+
+fn bright(color: vec3f) -> vec3f {
+    let brightness = max(color.r, max(color.g, color.b));
+    // only the part above the threshold glows, so the glow fades in instead of popping
+    let amount = max(brightness - bloom.params.x, 0.0) / max(1.0 - bloom.params.x, 0.0001);
+    return color * amount;
+}
+
+// writes a half size target, so each pixel covers four of the input. the threshold is applied to each before averaging,
+// so a single bright pixel still glows
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(t_input));
+    var color = vec3f(0.0, 0.0, 0.0);
+    color += bright(textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-0.5, -0.5)).rgb);
+    color += bright(textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.5, -0.5)).rgb);
+    color += bright(textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-0.5, 0.5)).rgb);
+    color += bright(textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.5, 0.5)).rgb);
+    return vec4f(color * 0.25, 1.0);
+}
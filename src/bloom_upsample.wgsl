@@ -0,0 +1,39 @@
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+}
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+// a 3x3 tent filter over the smaller level, added onto the bigger one by the blend state
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(t_input));
+    var color = textureSample(t_input, s_input, in.tex_coords).rgb * 4.0;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-1.0, 0.0)).rgb * 2.0;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(1.0, 0.0)).rgb * 2.0;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.0, -1.0)).rgb * 2.0;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(0.0, 1.0)).rgb * 2.0;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-1.0, -1.0)).rgb;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(1.0, -1.0)).rgb;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(-1.0, 1.0)).rgb;
+    color += textureSample(t_input, s_input, in.tex_coords + texel * vec2f(1.0, 1.0)).rgb;
+    return vec4f(color / 16.0, 1.0);
+}
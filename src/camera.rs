@@ -2,6 +2,11 @@ use winit::dpi::PhysicalSize;
 
 pub struct Camera {
     pub pos: cgmath::Point3<f32>,
+    // renders the world from the nearest whole internal pixel below pos, and leaves the rest to be shifted in by PresentPass,
+    // so pixel art doesn't shimmer as the camera scrolls
+    pub pixel_perfect: bool,
+    // the internal resolution, how many world units fit across and up the screen
+    pub resolution: (u32, u32),
 }
 #[repr(C)]
 // This is so we can store this in a buffer
@@ -20,14 +25,17 @@ impl CameraUniform {
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
             view: cgmath::Matrix4::identity().into(),
-            viewport_dimensions: [VIEWPORT_WIDTH, VIEWPORT_HEIGHT, MAX_DEPTH - MIN_DEPTH, 1.0],
+            viewport_dimensions: [DEFAULT_RESOLUTION.0 as f32, DEFAULT_RESOLUTION.1 as f32, MAX_DEPTH - MIN_DEPTH, 1.0],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix().into();
-        self.view = cgmath::Matrix4::from_translation((-camera.pos.x, -camera.pos.y, 0.0).into()).into();
-        println!("{:?}", self.view_proj);
+        let (pos, _) = camera.snapped();
+        self.view = cgmath::Matrix4::from_translation((-pos.x, -pos.y, 0.0).into()).into();
+        let (width, height) = camera.size();
+        self.viewport_dimensions[0] = width;
+        self.viewport_dimensions[1] = height;
     }
 }
 
@@ -39,21 +47,75 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
-pub const VIEWPORT_WIDTH: f32 = 320.0;
-pub const VIEWPORT_HEIGHT: f32 = 180.0;
+// the part of the world the camera can see
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewBounds {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl ViewBounds {
+    pub fn overlaps_rect(&self, min: (f32, f32), max: (f32, f32)) -> bool {
+        min.0 <= self.right && max.0 >= self.left && min.1 <= self.top && max.1 >= self.bottom
+    }
+    pub fn overlaps_circle(&self, center: (f32, f32), radius: f32) -> bool {
+        let closest = (center.0.clamp(self.left, self.right), center.1.clamp(self.bottom, self.top));
+        let (dx, dy) = (center.0 - closest.0, center.1 - closest.1);
+        dx * dx + dy * dy <= radius * radius
+    }
+}
+
+// how many things were queued in a frame, and how many were left after culling
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub submitted: usize,
+    pub drawn: usize,
+}
+
+pub const DEFAULT_RESOLUTION: (u32, u32) = (320, 180);
+// 16:9 at a few pixel densities, then the same heights at 21:9
+pub const RESOLUTION_PRESETS: [(u32, u32); 6] = [(320, 180), (384, 216), (480, 270), (420, 180), (504, 216), (630, 270)];
 pub const MAX_DEPTH: f32 = 500.0;
 pub const MIN_DEPTH: f32 = -500.0;
 impl Camera {
+    pub fn size(&self) -> (f32, f32) {
+        (self.resolution.0 as f32, self.resolution.1 as f32)
+    }
+
+    // the position the world is rendered from, and how far pos is past it in internal pixels
+    pub fn snapped(&self) -> (cgmath::Point3<f32>, (f32, f32)) {
+        if !self.pixel_perfect {
+            return (self.pos, (0.0, 0.0));
+        }
+        let (x, y) = (self.pos.x.floor(), self.pos.y.floor());
+        ((x, y, self.pos.z).into(), (self.pos.x - x, self.pos.y - y))
+    }
+
+    pub fn view_bounds(&self) -> ViewBounds {
+        let (pos, _) = self.snapped();
+        let (width, height) = self.size();
+        ViewBounds {
+            left: pos.x - width / 2.0,
+            right: pos.x + width / 2.0,
+            bottom: pos.y - height / 2.0,
+            top: pos.y + height / 2.0,
+        }
+    }
+
     fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         // 1.
-        let view = cgmath::Matrix4::from_translation((-self.pos.x, -self.pos.y, 0.0).into());
+        let (pos, _) = self.snapped();
+        let view = cgmath::Matrix4::from_translation((-pos.x, -pos.y, 0.0).into());
         // let rot = cgmath::Matrix4::from_angle_x(-self.angle);
         // 2.
+        let (width, height) = self.size();
         let proj = cgmath::ortho(
-            -VIEWPORT_WIDTH / 2.0,
-            VIEWPORT_WIDTH / 2.0,
-            -VIEWPORT_HEIGHT / 2.0,
-            VIEWPORT_HEIGHT / 2.0,
+            -width / 2.0,
+            width / 2.0,
+            -height / 2.0,
+            height / 2.0,
             MIN_DEPTH,
             MAX_DEPTH,
         );
@@ -0,0 +1,64 @@
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+}
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+
+struct Grading {
+    // weight, fade progress, size of the lut being faded from, size of the lut being faded to
+    params: vec4f,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(1) @binding(0)
+var t_from: texture_3d<f32>;
+@group(1) @binding(1)
+var t_to: texture_3d<f32>;
+@group(1) @binding(2)
+var s_lut: sampler;
+@group(2) @binding(0)
+var<uniform> grading: Grading;
+
+fn to_srgb(linear: vec3f) -> vec3f {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3f(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3f(0.0031308));
+}
+
+fn to_linear(srgb: vec3f) -> vec3f {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, srgb <= vec3f(0.04045));
+}
+
+// moves the lookup to the centres of the first and last entries, so the ends of the range aren't blended with the border
+fn lut_coords(color: vec3f, size: f32) -> vec3f {
+    return color * ((size - 1.0) / size) + 0.5 / size;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    // luts are authored on the colors as they appear on screen
+    let srgb = to_srgb(saturate(color.rgb));
+    let graded_from = textureSample(t_from, s_lut, lut_coords(srgb, grading.params.z)).rgb;
+    let graded_to = textureSample(t_to, s_lut, lut_coords(srgb, grading.params.w)).rgb;
+    let graded = mix(graded_from, graded_to, grading.params.y);
+    return vec4f(to_linear(mix(srgb, graded, grading.params.x)), color.a);
+}
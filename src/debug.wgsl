@@ -0,0 +1,42 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    dimensions: vec2f,
+};
+@group(0) @binding(0) // 1.
+var<uniform> camera: CameraUniform;
+
+struct DebugVertexInput {
+    @location(0) position: vec3f,
+    @location(1) color: vec4f,
+    @location(2) screen_space: f32,
+}
+struct DebugVertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec4f,
+}
+
+@vertex
+fn vs_main(
+    model: DebugVertexInput,
+) -> DebugVertexOutput {
+    var out: DebugVertexOutput;
+    out.color = model.color;
+    if model.screen_space > 0.5 {
+        // same screen space as the overlay pass
+        out.clip_position = vec4f(
+            model.position.x / camera.dimensions.x * 2.0 - 1.0,
+            1.0 - model.position.y / camera.dimensions.y * 2.0,
+            0.0,
+            1.0
+        );
+    } else {
+        out.clip_position = camera.view_proj * vec4f(model.position.xy, 0.0, 1.0);
+    }
+    return out;
+}
+
+@fragment
+fn fs_main(in: DebugVertexOutput) -> @location(0) vec4f {
+    return in.color;
+}
@@ -10,11 +10,17 @@ struct DeferredVertexInput {
     @location(0) position: vec3f,
     @location(1) albedo_coords: vec2f,
     @location(2) normal_coords: vec2f,
+    @location(3) flat_normal: vec4f,
+    @location(4) tint: vec4f,
+    @location(5) normal_rotation: vec4f,
 }
 struct DeferredVertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) albedo_coords: vec2f,
     @location(1) normal_coords: vec2f,
+    @location(2) flat_normal: vec4f,
+    @location(3) tint: vec4f,
+    @location(4) normal_rotation: vec4f,
 }
 
 @vertex
@@ -24,6 +30,9 @@ fn vs_main(
     var out: DeferredVertexOutput;
     out.albedo_coords = model.albedo_coords;
     out.normal_coords = model.normal_coords;
+    out.flat_normal = model.flat_normal;
+    out.tint = model.tint;
+    out.normal_rotation = model.normal_rotation;
     out.clip_position = camera.view_proj * vec4f(model.position[0], model.position[1], 0.0, 1.0);
     return out;
 }
@@ -40,7 +49,16 @@ var s_pair: sampler;
 @fragment
 fn fs_main(in: DeferredVertexOutput) -> DeferredFragmentOutput {
     var out: DeferredFragmentOutput;
-    out.albedo_color = textureSample(t_deferred, s_pair, in.albedo_coords);
+    out.albedo_color = textureSample(t_deferred, s_pair, in.albedo_coords) * in.tint;
     out.normal_color = textureSample(t_deferred, s_pair, in.normal_coords);
+    out.normal_color.a *= in.tint.a;
+    // sprites without a normal map use a single normal, masked by the albedo's alpha
+    if in.flat_normal.w > 0.5 {
+        out.normal_color = vec4f(in.flat_normal.xyz * 0.5 + vec3f(0.5, 0.5, 0.5), out.albedo_color.a);
+    }
+    // rotated and flipped sprites need their normals turned to match
+    let rotation = mat2x2f(in.normal_rotation.xy, in.normal_rotation.zw);
+    let normal = out.normal_color.xyz * 2.0 - vec3f(1.0, 1.0, 1.0);
+    out.normal_color = vec4f(vec3f(rotation * normal.xy, normal.z) * 0.5 + vec3f(0.5, 0.5, 0.5), out.normal_color.a);
     return out;
 }
\ No newline at end of file
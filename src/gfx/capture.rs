@@ -0,0 +1,148 @@
+use std::sync::mpsc;
+
+use anyhow::*;
+use image::RgbaImage;
+use wgpu::{Buffer, CommandEncoder, Device, TextureFormat};
+
+/*
+   Copying textures back from the GPU. Rows in a copy have to start on a multiple of COPY_BYTES_PER_ROW_ALIGNMENT,
+   so the buffer is wider than the image and the padding is stripped off once it's mapped.
+
+   Everything read back is a finished frame, which is opaque however the passes left the alpha channel
+   (the lighting pass writes 0 there), so images always come back with full alpha.
+*/
+
+// a texture copied into a buffer that can be mapped once the copy has been submitted
+pub struct Readback {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    // surfaces are often bgra, images are always rgba
+    bgra: bool,
+}
+
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+impl Readback {
+    // records the copy into encoder. the texture needs COPY_SRC and an 8 bit rgba or bgra format
+    pub fn new(device: &Device, encoder: &mut CommandEncoder, texture: &wgpu::Texture) -> Result<Readback> {
+        Readback::reusing(device, encoder, texture, None)
+    }
+
+    // like new, but copies into buffer instead of making a new one if it's from an earlier readback of the same size
+    pub fn reusing(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &wgpu::Texture,
+        buffer: Option<Buffer>,
+    ) -> Result<Readback> {
+        let bgra = match texture.format() {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            format => bail!("can't read back textures in {:?}", format),
+        };
+        let (width, height) = (texture.width(), texture.height());
+        let padded_bytes_per_row = padded_bytes_per_row(width);
+        let size = (padded_bytes_per_row * height) as u64;
+        let buffer = match buffer {
+            Some(buffer) if buffer.size() == size => buffer,
+            _ => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback_buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+        };
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        Result::Ok(Readback {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra,
+        })
+    }
+
+    // blocks until the copy has finished, so the encoder it was recorded in has to be submitted first
+    pub fn read(self, device: &Device) -> Result<RgbaImage> {
+        let mut pending = self.map();
+        device.poll(wgpu::Maintain::Wait);
+        pending.try_read().unwrap_or_else(|| Err(anyhow!("readback wasn't mapped after waiting for the GPU")))
+    }
+
+    // starts mapping the buffer without waiting for it, the encoder it was recorded in has to be submitted first
+    pub fn map(self) -> PendingReadback {
+        let (sender, receiver) = mpsc::channel();
+        self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        PendingReadback {
+            readback: self,
+            receiver,
+        }
+    }
+
+    fn to_image(&self, data: &[u8]) -> RgbaImage {
+        let row_bytes = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_bytes]);
+        }
+        for pixel in pixels.chunks_exact_mut(4) {
+            if self.bgra {
+                pixel.swap(0, 2);
+            }
+            pixel[3] = 255;
+        }
+        RgbaImage::from_raw(self.width, self.height, pixels).unwrap()
+    }
+}
+
+// a readback waiting for the GPU, which only finishes when the device is polled
+pub struct PendingReadback {
+    readback: Readback,
+    receiver: mpsc::Receiver<std::result::Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl PendingReadback {
+    // None if the copy hasn't finished yet
+    pub fn try_read(&mut self) -> Option<Result<RgbaImage>> {
+        let result = match self.receiver.try_recv() {
+            Result::Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => return Some(Err(anyhow!("readback was dropped before it was mapped"))),
+        };
+        Some(result.map_err(Error::from).map(|_| {
+            let buffer = &self.readback.buffer;
+            let image = self.readback.to_image(&buffer.slice(..).get_mapped_range());
+            buffer.unmap();
+            image
+        }))
+    }
+
+    // the buffer, unmapped again, to copy the next readback into. only usable once try_read has returned something
+    pub fn into_buffer(self) -> Buffer {
+        self.readback.buffer
+    }
+}
@@ -1,15 +1,114 @@
-use wgpu::InstanceDescriptor;
-use winit::dpi::PhysicalSize;
+use std::iter;
 
-pub struct GraphicsContext {
+use anyhow::*;
+use wgpu::{CommandEncoder, Device, InstanceDescriptor, Queue, TextureFormat, TextureView};
+
+use crate::texture::Texture;
+
+/*
+   The device and queue everything is drawn with, and where finished frames go.
+   Frames go to a window's surface in the game, or to an offscreen texture for tests and tools running without a display.
+*/
+
+pub trait PresentTarget {
+    fn size(&self) -> (u32, u32);
+    fn format(&self) -> TextureFormat;
+    fn resize(&mut self, device: &Device, width: u32, height: u32);
+    // submits encoder after draw has recorded the frame into the target's view, then shows it
+    fn present(
+        &mut self,
+        queue: &Queue,
+        encoder: CommandEncoder,
+        draw: impl FnOnce(&mut CommandEncoder, &TextureView),
+    ) -> Result<(), wgpu::SurfaceError>;
+}
+
+pub struct SurfaceTarget {
     pub surface: wgpu::Surface<'static>,
+    pub config: wgpu::SurfaceConfiguration,
+}
+
+impl PresentTarget for SurfaceTarget {
+    fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+    fn format(&self) -> TextureFormat {
+        self.config.format
+    }
+    fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(device, &self.config);
+    }
+    fn present(
+        &mut self,
+        queue: &Queue,
+        mut encoder: CommandEncoder,
+        draw: impl FnOnce(&mut CommandEncoder, &TextureView),
+    ) -> Result<(), wgpu::SurfaceError> {
+        let output_texture = self.surface.get_current_texture()?;
+        let output_view = output_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        draw(&mut encoder, &output_view);
+        queue.submit(iter::once(encoder.finish()));
+        output_texture.present();
+        Result::Ok(())
+    }
+}
+
+// keeps the last presented frame in a texture that can be copied back
+pub struct OffscreenTarget {
+    pub texture: Texture,
+}
+
+// the same srgb format a surface would usually pick, so offscreen frames match the window
+pub const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+impl PresentTarget for OffscreenTarget {
+    fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+    fn format(&self) -> TextureFormat {
+        OFFSCREEN_FORMAT
+    }
+    fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.texture = Texture::create_texture(device, Some("offscreen_target"), (width, height), OFFSCREEN_FORMAT).unwrap();
+    }
+    fn present(
+        &mut self,
+        queue: &Queue,
+        mut encoder: CommandEncoder,
+        draw: impl FnOnce(&mut CommandEncoder, &TextureView),
+    ) -> Result<(), wgpu::SurfaceError> {
+        draw(&mut encoder, &self.texture.view);
+        queue.submit(iter::once(encoder.finish()));
+        Result::Ok(())
+    }
+}
+
+pub struct GraphicsContext<T: PresentTarget> {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub config: wgpu::SurfaceConfiguration,
+    pub target: T,
 }
 
-impl GraphicsContext {
-    pub async fn new(window: &winit::window::Window) -> GraphicsContext {
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+    Result::Ok(
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+
+                    required_limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await?,
+    )
+}
+
+impl GraphicsContext<SurfaceTarget> {
+    pub async fn new(window: &winit::window::Window) -> GraphicsContext<SurfaceTarget> {
         let size = &window.inner_size();
 
         // The instance is a handle to our GPU
@@ -32,18 +131,7 @@ impl GraphicsContext {
             .unwrap();
 
         // Select a device to use
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-
-                    required_limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter).await.unwrap();
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps.formats.iter()
             .find(|f| f.is_srgb())
@@ -64,15 +152,48 @@ impl GraphicsContext {
         surface.configure(&device, &config);
 
         GraphicsContext {
-            surface,
             device,
             queue,
-            config,
+            target: SurfaceTarget { surface, config },
         }
     }
+}
+
+#[allow(dead_code)]
+impl GraphicsContext<OffscreenTarget> {
+    // uses any adapter it can find, falling back to a software one when there's no GPU.
+    // fails if there isn't even that, which the golden image tests treat as a failure rather than skipping
+    pub async fn headless(size: (u32, u32)) -> Result<GraphicsContext<OffscreenTarget>> {
+        let instance = wgpu::Instance::new(InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let mut adapter = None;
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.context("no graphics adapter available, not even a software one")?;
+        let (device, queue) = request_device(&adapter).await?;
+        let texture = Texture::create_texture(&device, Some("offscreen_target"), size, OFFSCREEN_FORMAT)?;
+        Result::Ok(GraphicsContext {
+            device,
+            queue,
+            target: OffscreenTarget { texture },
+        })
+    }
+}
+
+impl<T: PresentTarget> GraphicsContext<T> {
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.config.width = width;
-        self.config.height = height;
-        self.surface.configure(&self.device, &self.config);
+        self.target.resize(&self.device, width, height);
     }
 }
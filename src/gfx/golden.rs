@@ -0,0 +1,189 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use super::{
+    context::OffscreenTarget,
+    pass::{
+        deferred_pass::SpriteTransform,
+        lighting_pass::{Color, DynamicLight},
+    },
+};
+use crate::{
+    assets::TextureAtlasStorage,
+    renderer::{RendererState, ScreenshotSource},
+    texture_atlas::Region,
+};
+
+/*
+   Golden image tests render small canned scenes offscreen and compare the lit scene against reference pngs in tests/golden,
+   so changes to the lighting shaders that move or flip the light show up as failures instead of in the game.
+
+   A pixel fails if any channel is further than TOLERANCE from the reference, which leaves room for different adapters rounding differently.
+   Failing scenes write what was rendered and a diff image into target/golden, with the failing pixels in red over a faded copy of the reference.
+
+   Run with UPDATE_GOLDEN=1 to replace the references after an intended change. The tests fail if there's no adapter at all,
+   so a machine that can't render has to install a software one (like lavapipe or llvmpipe) rather than pass without checking anything.
+*/
+
+const RESOLUTION: (u32, u32) = (64, 48);
+const TOLERANCE: u8 = 3;
+
+fn references() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+fn failures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+// a renderer with no post effects at the test resolution, and the game's tiles and entities
+fn renderer() -> (RendererState<OffscreenTarget>, HashMap<String, Region>) {
+    let mut storage = TextureAtlasStorage::new();
+    let mut renderer = match pollster::block_on(RendererState::headless(RESOLUTION, &mut storage)) {
+        Ok(renderer) => renderer,
+        Err(err) => panic!("couldn't make a renderer for golden image tests, they need at least a software adapter: {:#}", err),
+    };
+    renderer.set_internal_resolution(RESOLUTION);
+    let textures = storage.load("textures", &renderer.texture_creator()).unwrap();
+    let mut regions = textures.get_region("tiles").unwrap().unwrap_atlas();
+    regions.extend(textures.get_region("entities").unwrap().unwrap_atlas());
+    (renderer, regions)
+}
+
+// renders one frame of the scene and checks it against the reference called name
+fn check_scene(name: &str, draw: impl FnOnce(&mut RendererState<OffscreenTarget>, &HashMap<String, Region>)) {
+    let (mut renderer, regions) = renderer();
+    draw(&mut renderer, &regions);
+    renderer.render().unwrap();
+    let actual = renderer.read_frame(ScreenshotSource::Native).unwrap();
+    // a transparent capture shows up blank in most image viewers
+    assert!(actual.pixels().all(|pixel| pixel[3] == 255), "{} was captured with transparent pixels", name);
+
+    let reference_path = references().join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(references()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.to_rgba8(),
+        Err(err) => panic!("couldn't open {}: {}, run with UPDATE_GOLDEN=1 to create it", reference_path.display(), err),
+    };
+    assert_eq!(reference.dimensions(), actual.dimensions(), "{} is a different size to its reference", name);
+
+    let (diff, failed) = diff(&reference, &actual);
+    if failed > 0 {
+        std::fs::create_dir_all(failures()).unwrap();
+        let actual_path = failures().join(format!("{}.png", name));
+        let diff_path = failures().join(format!("{}_diff.png", name));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{} pixels of {} were off by more than {}, see {} and {}",
+            failed,
+            name,
+            TOLERANCE,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+// the reference faded to a quarter, with every pixel that's out of tolerance in red, and how many there were
+fn diff(reference: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, usize) {
+    let mut failed = 0;
+    let diff = RgbaImage::from_fn(reference.width(), reference.height(), |x, y| {
+        let (expected, got) = (reference.get_pixel(x, y), actual.get_pixel(x, y));
+        let off = expected.0.iter().zip(got.0).any(|(&expected, got)| expected.abs_diff(got) > TOLERANCE);
+        if off {
+            failed += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([expected[0] / 4, expected[1] / 4, expected[2] / 4, 255])
+        }
+    });
+    (diff, failed)
+}
+
+// covers the whole view in floor tiles facing the camera
+fn draw_floor(renderer: &mut RendererState<OffscreenTarget>, regions: &HashMap<String, Region>) {
+    let (width, height) = (RESOLUTION.0 as f32, RESOLUTION.1 as f32);
+    renderer.draw_tinted_sprite((-width / 2.0, -height / 2.0, 0.0), (width, height), [1.0; 4], regions["floor"].unwrap_single()).unwrap();
+}
+
+#[test]
+fn single_light() {
+    check_scene("single_light", |renderer, regions| {
+        draw_floor(renderer, regions);
+        renderer.draw_light(DynamicLight {
+            center: (0.0, 0.0, 10.0),
+            radius: 20.0,
+            color: Color::from_rgb(255, 255, 255),
+        });
+    });
+}
+
+#[test]
+fn colored_overlap() {
+    check_scene("colored_overlap", |renderer, regions| {
+        draw_floor(renderer, regions);
+        for (center, color) in [
+            ((-8.0, 4.0), Color::from_rgb(255, 0, 0)),
+            ((8.0, 4.0), Color::from_rgb(0, 255, 0)),
+            ((0.0, -8.0), Color::from_rgb(0, 0, 255)),
+        ] {
+            renderer.draw_light(DynamicLight {
+                center: (center.0, center.1, 10.0),
+                radius: 16.0,
+                color,
+            });
+        }
+    });
+}
+
+// each quarter of the floor faces the light in the middle, so a flipped axis leaves two of them dark.
+// the zombie's normal map in the middle checks maps are read the right way up too
+#[test]
+fn normal_map_orientation() {
+    check_scene("normal_map_orientation", |renderer, regions| {
+        let (width, height) = (RESOLUTION.0 as f32 / 2.0, RESOLUTION.1 as f32 / 2.0);
+        for (corner, normal) in [
+            ((-width, 0.0), [0.6, -0.6, 0.53]),
+            ((0.0, 0.0), [-0.6, -0.6, 0.53]),
+            ((-width, -height), [0.6, 0.6, 0.53]),
+            ((0.0, -height), [-0.6, 0.6, 0.53]),
+        ] {
+            renderer.set_default_normal(normal);
+            renderer.draw_tinted_sprite((corner.0, corner.1, 0.0), (width, height), [1.0; 4], regions["floor"].unwrap_single()).unwrap();
+        }
+        renderer.draw_tinted_deferred_sprite((-8.0, -8.0, 0.0), (16.0, 16.0), [1.0; 4], regions["zombie"].unwrap_pair()).unwrap();
+        renderer.draw_light(DynamicLight {
+            center: (0.0, 0.0, 10.0),
+            radius: 40.0,
+            color: Color::from_rgb(255, 255, 255),
+        });
+    });
+}
+
+// the zombie drawn as is, flipped horizontally, vertically and both ways, so a flip that winds the quad backwards
+// and gets it culled leaves a hole
+#[test]
+fn flipped_sprites() {
+    check_scene("flipped_sprites", |renderer, regions| {
+        draw_floor(renderer, regions);
+        for (position, scale) in [
+            ((-16.0, 2.0), (1.0, 1.0)),
+            ((16.0, 2.0), (-1.0, 1.0)),
+            ((-16.0, -2.0), (1.0, -1.0)),
+            ((16.0, -2.0), (-1.0, -1.0)),
+        ] {
+            let transform = SpriteTransform { rotation: 0.0, scale };
+            renderer.draw_deferred_sprite_transformed((position.0, position.1, 0.0), regions["zombie"].unwrap_pair(), transform).unwrap();
+        }
+        renderer.draw_light(DynamicLight {
+            center: (0.0, 0.0, 10.0),
+            radius: 40.0,
+            color: Color::from_rgb(255, 255, 255),
+        });
+    });
+}
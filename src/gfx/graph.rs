@@ -0,0 +1,269 @@
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use anyhow::*;
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Id, Queue, TextureFormat};
+
+use crate::{camera::ViewBounds, texture::Texture};
+
+/*
+   The render graph decides what order passes run in from the textures they read and write, and owns those textures.
+
+   Textures are declared once by name with their size and format, and only created when a pass in the graph uses them.
+   They're kept between frames and only recreated when their description changes.
+
+   For each texture, the passes that write it without reading it run first, then the passes that draw on top of it
+   in the order they were added, then the passes that only read it.
+*/
+
+// the targets the built in passes use, all at the internal resolution.
+// albedo and normal are the deferred pass's g-buffer
+pub const ALBEDO: &str = "albedo";
+pub const NORMAL: &str = "normal";
+// the lighting pass's output
+pub const LIT: &str = "lit";
+// the lit scene after post effects, with overlays on top, which is what gets presented
+pub const SCENE: &str = "scene";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TargetDesc {
+    pub size: (u32, u32),
+    pub format: TextureFormat,
+}
+
+// what every pass gets to know about the frame
+pub struct FrameData<'a> {
+    pub camera_bind_group: &'a BindGroup,
+    pub view: ViewBounds,
+}
+
+pub struct NodeContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub encoder: &'a mut CommandEncoder,
+    pub targets: &'a RenderTargets,
+    pub frame: &'a FrameData<'a>,
+}
+
+pub trait RenderNode {
+    fn name(&self) -> &str;
+    // textures this node samples, or draws on top of without clearing
+    fn reads(&self) -> Vec<&'static str>;
+    fn writes(&self) -> Vec<&'static str>;
+    fn run(&mut self, context: &mut NodeContext);
+}
+
+type BindGroupKey = (Id<BindGroupLayout>, Vec<&'static str>);
+
+#[derive(Default)]
+pub struct RenderTargets {
+    descs: HashMap<&'static str, TargetDesc>,
+    textures: HashMap<&'static str, Texture>,
+    // keyed by the layout and the textures in it, cleared whenever a texture is recreated
+    bind_groups: RefCell<HashMap<BindGroupKey, Arc<BindGroup>>>,
+}
+
+impl RenderTargets {
+    pub fn new() -> RenderTargets {
+        RenderTargets::default()
+    }
+
+    // declaring a texture again with a different description recreates it the next time it's used
+    pub fn declare(&mut self, name: &'static str, desc: TargetDesc) {
+        if self.descs.insert(name, desc).is_some_and(|old| old != desc) {
+            self.textures.remove(name);
+            self.bind_groups.borrow_mut().clear();
+        }
+    }
+
+    // panics if no node in the graph used the texture, which RenderGraph::execute makes sure of for every node it runs
+    pub fn texture(&self, name: &str) -> &Texture {
+        self.textures
+            .get(name)
+            .unwrap_or_else(|| panic!("render target {} wasn't used by the graph", name))
+    }
+
+    // textures from the last frame, or None if the texture hasn't been used yet
+    pub fn get(&self, name: &str) -> Option<&Texture> {
+        self.textures.get(name)
+    }
+
+    // a bind group with the named textures at bindings 0 onwards, followed by the first texture's sampler.
+    // made the first time it's asked for and reused until one of the textures is recreated
+    pub fn bind_group(&self, device: &Device, layout: &BindGroupLayout, names: &[&'static str]) -> Arc<BindGroup> {
+        let key = (layout.global_id(), names.to_vec());
+        self.bind_groups
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| {
+                let textures: Vec<&Texture> = names.iter().map(|name| self.texture(name)).collect();
+                let mut entries: Vec<wgpu::BindGroupEntry> = textures
+                    .iter()
+                    .enumerate()
+                    .map(|(binding, texture)| wgpu::BindGroupEntry {
+                        binding: binding as u32,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    })
+                    .collect();
+                entries.push(wgpu::BindGroupEntry {
+                    binding: textures.len() as u32,
+                    resource: wgpu::BindingResource::Sampler(&textures[0].sampler),
+                });
+                Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("render_graph_bind_group"),
+                    layout,
+                    entries: &entries,
+                }))
+            })
+            .clone()
+    }
+
+    fn allocate(&mut self, device: &Device, name: &'static str) -> Result<()> {
+        if self.textures.contains_key(name) {
+            return Ok(());
+        }
+        let Some(desc) = self.descs.get(name) else {
+            bail!("render target {} was never declared", name);
+        };
+        let texture = Texture::create_texture(device, Some(name), desc.size, desc.format)?;
+        self.textures.insert(name, texture);
+        Ok(())
+    }
+}
+
+// built every frame from the passes that should run, which is cheap next to actually running them
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    nodes: Vec<&'a mut dyn RenderNode>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> RenderGraph<'a> {
+        RenderGraph { nodes: Vec::new() }
+    }
+
+    pub fn add(&mut self, node: &'a mut dyn RenderNode) {
+        self.nodes.push(node);
+    }
+
+    // the order to run the nodes in, or an error naming the nodes if they depend on each other in a loop
+    pub fn order(&self) -> Result<Vec<usize>> {
+        let accesses: Vec<(Vec<&str>, Vec<&str>)> = self.nodes.iter().map(|node| (node.reads(), node.writes())).collect();
+        let mut after: Vec<Vec<usize>> = vec![Vec::new(); self.nodes.len()];
+        for (node, (reads, writes)) in accesses.iter().enumerate() {
+            for name in reads.iter() {
+                let modifies = writes.contains(name);
+                for (other, (other_reads, other_writes)) in accesses.iter().enumerate() {
+                    if other == node || !other_writes.contains(name) {
+                        continue;
+                    }
+                    let other_modifies = other_reads.contains(name);
+                    // creators go before anything drawing on top, which go in the order they were added
+                    let depends = !modifies || !other_modifies || other < node;
+                    if depends {
+                        after[node].push(other);
+                    }
+                }
+            }
+        }
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut done = vec![false; self.nodes.len()];
+        while order.len() < self.nodes.len() {
+            // the earliest added node that's ready, so unrelated passes keep the order they were added in
+            let Some(next) = (0..self.nodes.len()).find(|&node| !done[node] && after[node].iter().all(|&other| done[other])) else {
+                let stuck: Vec<&str> = (0..self.nodes.len()).filter(|&node| !done[node]).map(|node| self.nodes[node].name()).collect();
+                bail!("render graph has a cycle between {}", stuck.join(", "));
+            };
+            done[next] = true;
+            order.push(next);
+        }
+        Ok(order)
+    }
+
+    pub fn execute(self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, targets: &mut RenderTargets, frame: &FrameData) -> Result<()> {
+        let order = self.order()?;
+        for node in self.nodes.iter() {
+            for name in node.reads().into_iter().chain(node.writes()) {
+                targets.allocate(device, name).with_context(|| format!("in render node {}", node.name()))?;
+            }
+        }
+        let mut nodes: Vec<Option<&'a mut dyn RenderNode>> = self.nodes.into_iter().map(Some).collect();
+        let mut context = NodeContext {
+            device,
+            queue,
+            encoder,
+            targets,
+            frame,
+        };
+        for index in order {
+            nodes[index].take().unwrap().run(&mut context);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestNode {
+        name: &'static str,
+        reads: Vec<&'static str>,
+        writes: Vec<&'static str>,
+    }
+
+    impl RenderNode for TestNode {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn reads(&self) -> Vec<&'static str> {
+            self.reads.clone()
+        }
+        fn writes(&self) -> Vec<&'static str> {
+            self.writes.clone()
+        }
+        fn run(&mut self, _: &mut NodeContext) {
+            unreachable!("ordering never runs nodes")
+        }
+    }
+
+    fn node(name: &'static str, reads: &[&'static str], writes: &[&'static str]) -> TestNode {
+        TestNode {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        }
+    }
+
+    fn order(nodes: &mut [TestNode]) -> Result<Vec<&'static str>> {
+        let names: Vec<&'static str> = nodes.iter().map(|node| node.name).collect();
+        let mut graph = RenderGraph::new();
+        for node in nodes.iter_mut() {
+            graph.add(node);
+        }
+        Ok(graph.order()?.into_iter().map(|index| names[index]).collect())
+    }
+
+    #[test]
+    fn creators_then_modifiers_in_add_order_then_readers() {
+        let mut nodes = [
+            node("reader", &["a"], &["b"]),
+            node("first modifier", &["a"], &["a"]),
+            node("second modifier", &["a"], &["a"]),
+            node("creator", &[], &["a"]),
+        ];
+        assert_eq!(order(&mut nodes).unwrap(), ["creator", "first modifier", "second modifier", "reader"]);
+    }
+
+    #[test]
+    fn unrelated_nodes_keep_their_add_order() {
+        let mut nodes = [node("second", &[], &["b"]), node("first", &[], &["a"]), node("reader", &["a"], &["c"])];
+        assert_eq!(order(&mut nodes).unwrap(), ["second", "first", "reader"]);
+    }
+
+    #[test]
+    fn cycles_are_errors_naming_the_nodes() {
+        let mut nodes = [node("ping", &["a"], &["b"]), node("pong", &["b"], &["a"]), node("fine", &[], &["c"])];
+        let err = order(&mut nodes).unwrap_err().to_string();
+        assert!(err.contains("ping") && err.contains("pong") && !err.contains("fine"), "{}", err);
+    }
+}
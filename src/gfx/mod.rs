@@ -1,3 +1,10 @@
+pub mod capture;
 pub mod context;
+pub mod graph;
 pub mod pass;
-pub mod pipeline;
\ No newline at end of file
+pub mod pipeline;
+pub mod post;
+pub mod recording;
+
+#[cfg(test)]
+mod golden;
@@ -0,0 +1,211 @@
+use crate::gfx::pass::overlay_pass::Space;
+#[cfg(feature = "debug-draw")]
+use crate::{gfx::{graph::{NodeContext, RenderNode, SCENE}, pipeline::Pipeline}, texture::Texture, Vertex};
+#[cfg(feature = "debug-draw")]
+use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BlendState, ColorTargetState, ColorWrites, CommandEncoder, Device, RenderPassDescriptor};
+
+/*
+   Immediate mode debug shapes, drawn as 1 pixel lines on top of everything at the internal resolution and cleared every frame.
+
+   Without the debug-draw feature, DebugDraw keeps the same api but throws everything away, and there is no DebugPass.
+*/
+
+#[derive(Default)]
+pub struct DebugDraw {
+    #[cfg(feature = "debug-draw")]
+    lines: Vec<DebugVertex>,
+    #[cfg(feature = "debug-draw")]
+    points: Vec<DebugVertex>,
+}
+
+#[cfg(feature = "debug-draw")]
+impl DebugDraw {
+    fn vertex(position: (f32, f32), color: [f32; 4], space: Space) -> DebugVertex {
+        DebugVertex {
+            position: [position.0, position.1, 0.0],
+            color,
+            screen_space: match space {
+                Space::World => 0.0,
+                Space::Screen => 1.0,
+            },
+        }
+    }
+    #[inline]
+    fn push_line(&mut self, from: (f32, f32), to: (f32, f32), color: [f32; 4], space: Space) {
+        self.lines.push(DebugDraw::vertex(from, color, space));
+        self.lines.push(DebugDraw::vertex(to, color, space));
+    }
+    #[inline]
+    fn push_point(&mut self, position: (f32, f32), color: [f32; 4], space: Space) {
+        self.points.push(DebugDraw::vertex(position, color, space));
+    }
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.points.clear();
+    }
+}
+
+#[cfg(not(feature = "debug-draw"))]
+impl DebugDraw {
+    #[inline]
+    fn push_line(&mut self, _from: (f32, f32), _to: (f32, f32), _color: [f32; 4], _space: Space) {}
+    #[inline]
+    fn push_point(&mut self, _position: (f32, f32), _color: [f32; 4], _space: Space) {}
+}
+
+#[allow(dead_code)]
+impl DebugDraw {
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), color: [f32; 4], space: Space) {
+        self.push_line(from, to, color, space);
+    }
+    // position is the bottom left in world space and the top left in screen space, like sprites
+    pub fn rect(&mut self, position: (f32, f32), size: (f32, f32), color: [f32; 4], space: Space) {
+        let (x, y) = position;
+        let (width, height) = size;
+        self.push_line((x, y), (x + width, y), color, space);
+        self.push_line((x + width, y), (x + width, y + height), color, space);
+        self.push_line((x + width, y + height), (x, y + height), color, space);
+        self.push_line((x, y + height), (x, y), color, space);
+    }
+    pub fn circle(&mut self, center: (f32, f32), radius: f32, color: [f32; 4], space: Space) {
+        // roughly one segment every 4 pixels of circumference
+        let segments = ((std::f32::consts::TAU * radius / 4.0) as usize).clamp(8, 64);
+        let point = |index: usize| {
+            let angle = index as f32 / segments as f32 * std::f32::consts::TAU;
+            (center.0 + angle.cos() * radius, center.1 + angle.sin() * radius)
+        };
+        for index in 0..segments {
+            self.push_line(point(index), point(index + 1), color, space);
+        }
+    }
+    pub fn arrow(&mut self, from: (f32, f32), to: (f32, f32), head_size: f32, color: [f32; 4], space: Space) {
+        self.push_line(from, to, color, space);
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        let (dx, dy) = (dx / length * head_size, dy / length * head_size);
+        // the two sides of the head are the direction turned back by 150 degrees either way
+        let (sin, cos) = (0.5, -(3.0f32.sqrt()) / 2.0);
+        self.push_line(to, (to.0 + dx * cos - dy * sin, to.1 + dx * sin + dy * cos), color, space);
+        self.push_line(to, (to.0 + dx * cos + dy * sin, to.1 - dx * sin + dy * cos), color, space);
+    }
+    pub fn point(&mut self, position: (f32, f32), color: [f32; 4], space: Space) {
+        self.push_point(position, color, space);
+    }
+}
+
+#[cfg(feature = "debug-draw")]
+pub struct DebugPass {
+    line_pipeline: Pipeline,
+    point_pipeline: Pipeline,
+}
+
+#[cfg(feature = "debug-draw")]
+impl DebugPass {
+    pub fn new(device: &Device, format: wgpu::TextureFormat) -> DebugPass {
+        let camera_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("camera_bind_group_layout"),
+        });
+        let targets = &[Some(ColorTargetState {
+            format,
+            blend: Some(BlendState::ALPHA_BLENDING),
+            write_mask: ColorWrites::ALL,
+        })];
+        let line_pipeline = Pipeline::with_topology::<DebugVertex>(device, include_wgsl!("../../debug.wgsl"), &[&camera_bind_group_layout], targets, wgpu::PrimitiveTopology::LineList, "DebugPass lines");
+        let point_pipeline = Pipeline::with_topology::<DebugVertex>(device, include_wgsl!("../../debug.wgsl"), &[&camera_bind_group_layout], targets, wgpu::PrimitiveTopology::PointList, "DebugPass points");
+        DebugPass {
+            line_pipeline,
+            point_pipeline,
+        }
+    }
+
+    pub fn draw(&self, device: &Device, encoder: &mut CommandEncoder, camera_bind_group: &BindGroup, target: &Texture, shapes: &mut DebugDraw) {
+        if shapes.lines.is_empty() && shapes.points.is_empty() {
+            return;
+        }
+        let line_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Line Buffer"),
+            contents: bytemuck::cast_slice(shapes.lines.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let point_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Point Buffer"),
+            contents: bytemuck::cast_slice(shapes.points.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let mut debug_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("debug_pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+                ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        debug_pass.set_bind_group(0, camera_bind_group, &[]);
+        if !shapes.lines.is_empty() {
+            debug_pass.set_pipeline(&self.line_pipeline.pipeline);
+            debug_pass.set_vertex_buffer(0, line_buffer.slice(..));
+            debug_pass.draw(0..shapes.lines.len() as u32, 0..1);
+        }
+        if !shapes.points.is_empty() {
+            debug_pass.set_pipeline(&self.point_pipeline.pipeline);
+            debug_pass.set_vertex_buffer(0, point_buffer.slice(..));
+            debug_pass.draw(0..shapes.points.len() as u32, 0..1);
+        }
+        std::mem::drop(debug_pass);
+        shapes.clear();
+    }
+}
+
+// draws the queued shapes on top of the scene as part of the render graph
+#[cfg(feature = "debug-draw")]
+pub struct DebugNode<'a> {
+    pub pass: &'a DebugPass,
+    pub shapes: &'a mut DebugDraw,
+}
+
+#[cfg(feature = "debug-draw")]
+impl RenderNode for DebugNode<'_> {
+    fn name(&self) -> &str {
+        "debug"
+    }
+    fn reads(&self) -> Vec<&'static str> {
+        vec![SCENE]
+    }
+    fn writes(&self) -> Vec<&'static str> {
+        vec![SCENE]
+    }
+    fn run(&mut self, context: &mut NodeContext) {
+        self.pass.draw(context.device, context.encoder, context.frame.camera_bind_group, context.targets.texture(SCENE), self.shapes);
+    }
+}
+
+#[cfg(feature = "debug-draw")]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+    screen_space: f32,
+}
@@ -1,31 +1,138 @@
 use crate::{camera::CameraUniform, Vertex};
+use anyhow::{bail, Result};
 use std::{rc::Rc, sync::Arc};
 
 use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BindGroupDescriptor, BindGroupLayout, BlendState, ColorTargetState, CommandEncoder, Device};
 
-use crate::{camera::{VIEWPORT_HEIGHT, VIEWPORT_WIDTH}, texture::Texture, texture_atlas::DeferredTextureRegion};
+use crate::{camera::{CullStats, ViewBounds}, texture::Texture, texture_atlas::{DeferredTextureRegion, Rect, TextureRegion}};
 
-use super::super::pipeline::Pipeline;
+use super::super::{graph::{NodeContext, RenderNode, ALBEDO, NORMAL}, pipeline::Pipeline};
+
+// normal used for sprites that don't have a normal map, pointing straight out of the screen
+pub const FLAT_NORMAL: [f32; 3] = [0.0, 0.0, 1.0];
+// a zero length normal marks the albedo as unlit, so the lighting pass shows it at full brightness
+pub const UNLIT_NORMAL: [f32; 3] = [0.0, 0.0, 0.0];
+
+// applied around a sprite's pivot, rotation is in radians counterclockwise
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteTransform {
+    pub rotation: f32,
+    // negative scales flip the sprite
+    pub scale: (f32, f32),
+}
+impl Default for SpriteTransform {
+    fn default() -> Self {
+        SpriteTransform {
+            rotation: 0.0,
+            scale: (1.0, 1.0),
+        }
+    }
+}
 
 pub struct DeferredPass {
     sprites: DeferredSpriteBatch,
+    pub default_normal: [f32; 3],
     deferred_texture_bind_group: BindGroup,
     sheet: Arc<Texture>,
     pipeline: Pipeline,
-    pub albedo_buffer: Texture,
-    pub normal_buffer: Texture,
+    // how many sprites were culled in the last frame
+    pub stats: CullStats,
 }
 impl DeferredPass {
+    // the sprite's pivot is placed at position
     pub fn draw_sprite(
         &mut self,
         position: (f32, f32, f32),
         image: DeferredTextureRegion,
-    ) {
-        if self.sheet == image.texture {
-            self.sprites.sprites.push((position, image));
-        } else {
-            panic!("sprite had wrong sheet!")
+        transform: SpriteTransform,
+    ) -> Result<()> {
+        self.push_sprite(position, &image.texture, DeferredSprite {
+            albedo: image.albedo,
+            normal: SpriteNormal::Map(image.normal),
+            size: (image.albedo.width as f32, image.albedo.height as f32),
+            tint: [1.0; 4],
+            pivot: image.pivot,
+            transform,
+        })
+    }
+    pub fn draw_single_sprite(
+        &mut self,
+        position: (f32, f32, f32),
+        image: TextureRegion,
+        normal: Option<[f32; 3]>,
+        transform: SpriteTransform,
+    ) -> Result<()> {
+        self.push_sprite(position, &image.texture, DeferredSprite {
+            albedo: image.src,
+            normal: SpriteNormal::Flat(normal.unwrap_or(self.default_normal)),
+            size: (image.src.width as f32, image.src.height as f32),
+            tint: [1.0; 4],
+            pivot: image.pivot,
+            transform,
+        })
+    }
+    // stretches the sprite to size and multiplies its albedo by tint, using the default normal.
+    // sized sprites ignore their pivot, position is always the bottom left
+    pub fn draw_tinted_sprite(
+        &mut self,
+        position: (f32, f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        image: TextureRegion,
+    ) -> Result<()> {
+        self.push_sprite(position, &image.texture, DeferredSprite {
+            albedo: image.src,
+            normal: SpriteNormal::Flat(self.default_normal),
+            size,
+            tint,
+            pivot: (0.0, 0.0),
+            transform: SpriteTransform::default(),
+        })
+    }
+    // like draw_tinted_sprite, but keeps the sprite's normal map
+    pub fn draw_tinted_pair(
+        &mut self,
+        position: (f32, f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        image: DeferredTextureRegion,
+    ) -> Result<()> {
+        self.push_sprite(position, &image.texture, DeferredSprite {
+            albedo: image.albedo,
+            normal: SpriteNormal::Map(image.normal),
+            size,
+            tint,
+            pivot: (0.0, 0.0),
+            transform: SpriteTransform::default(),
+        })
+    }
+    // like draw_tinted_sprite, but ignores lights and the global light
+    pub fn draw_unlit_sprite(
+        &mut self,
+        position: (f32, f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        image: TextureRegion,
+    ) -> Result<()> {
+        self.push_sprite(position, &image.texture, DeferredSprite {
+            albedo: image.src,
+            normal: SpriteNormal::Flat(UNLIT_NORMAL),
+            size,
+            tint,
+            pivot: (0.0, 0.0),
+            transform: SpriteTransform::default(),
+        })
+    }
+    // everything drawn in the deferred pass has to come from the one sheet it was made with
+    pub fn is_sheet(&self, texture: &Arc<Texture>) -> bool {
+        self.sheet == *texture
+    }
+    fn push_sprite(&mut self, position: (f32, f32, f32), texture: &Arc<Texture>, sprite: DeferredSprite) -> Result<()> {
+        if !self.is_sheet(texture) {
+            bail!("sprite isn't on the sheet the deferred pass draws from");
         }
+        self.sprites.sprites.push((position, sprite));
+        Ok(())
     }
     pub fn new(device: &Device, sheet: Arc<Texture>) -> DeferredPass {
         let deferred_texture_bind_group_layout =
@@ -93,22 +200,24 @@ impl DeferredPass {
         })
         ], "DeferredPass");
         
-        
-        let albedo_buffer = Texture::create_texture(&device, Some("albedo_buffer"), (VIEWPORT_WIDTH as u32, VIEWPORT_HEIGHT as u32), wgpu::TextureFormat::Rgba8Unorm).unwrap();
-        let normal_buffer = Texture::create_texture(&device, Some("normal_buffer"), (VIEWPORT_WIDTH as u32, VIEWPORT_HEIGHT as u32), wgpu::TextureFormat::Rgba8Unorm).unwrap();
         DeferredPass {
             sprites: DeferredSpriteBatch::new(),
+            default_normal: FLAT_NORMAL,
             deferred_texture_bind_group,
             sheet,
             pipeline, 
-            albedo_buffer,
-            normal_buffer,
+            stats: CullStats::default(),
         }
     }
     
-    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, camera_bind_group: &BindGroup) {
+    // sprites entirely outside of view are skipped
+    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, camera_bind_group: &BindGroup, view: &ViewBounds, albedo_buffer: &Texture, normal_buffer: &Texture) -> CullStats {
         
-        let (vertices, indices) = self.sprites.gen_vecs();
+        let (vertices, indices) = self.sprites.gen_vecs(&self.sheet, view);
+        let stats = CullStats {
+            submitted: self.sprites.sprites.len(),
+            drawn: vertices.len() / 4,
+        };
         let vertex_buffer = device
         .create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -127,7 +236,7 @@ impl DeferredPass {
             label: Some("G-Buffer Pass"),
             color_attachments: &[
             Some(wgpu::RenderPassColorAttachment {
-                view: &self.albedo_buffer.view,
+                view: &albedo_buffer.view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -140,7 +249,7 @@ impl DeferredPass {
                 },
             }),
             Some(wgpu::RenderPassColorAttachment {
-                view: &self.normal_buffer.view,
+                view: &normal_buffer.view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -165,12 +274,47 @@ impl DeferredPass {
         deferred_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         deferred_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
         self.sprites.sprites.clear();
+        stats
+    }
+
+    // forgets everything queued without drawing it
+    pub fn discard(&mut self) {
+        self.sprites.sprites.clear();
     }
-    
+}
+
+impl RenderNode for DeferredPass {
+    fn name(&self) -> &str {
+        "deferred"
+    }
+    fn reads(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    fn writes(&self) -> Vec<&'static str> {
+        vec![ALBEDO, NORMAL]
+    }
+    fn run(&mut self, context: &mut NodeContext) {
+        let (albedo, normal) = (context.targets.texture(ALBEDO), context.targets.texture(NORMAL));
+        self.stats = self.draw(context.device, context.encoder, context.frame.camera_bind_group, &context.frame.view, albedo, normal);
+    }
+}
+
+enum SpriteNormal {
+    Map(Rect),
+    Flat([f32; 3]),
+}
+struct DeferredSprite {
+    albedo: Rect,
+    normal: SpriteNormal,
+    size: (f32, f32),
+    tint: [f32; 4],
+    // from the bottom left, in the same units as size
+    pivot: (f32, f32),
+    transform: SpriteTransform,
 }
 
 struct DeferredSpriteBatch {
-    sprites: Vec<((f32, f32, f32), DeferredTextureRegion)>,
+    sprites: Vec<((f32, f32, f32), DeferredSprite)>,
 }
 impl DeferredSpriteBatch {
     fn new() -> DeferredSpriteBatch {
@@ -178,70 +322,49 @@ impl DeferredSpriteBatch {
             sprites: Vec::new(),
         }
     }
-    fn gen_vecs(&self) -> (Vec<DeferredVertex>, Vec<u16>) {
+    fn gen_vecs(&self, sheet: &Texture, view: &ViewBounds) -> (Vec<DeferredVertex>, Vec<u16>) {
         let mut vertices = Vec::new();
         let mut indices: Vec<u16> = Vec::new();
-        for (index, (position, region)) in self.sprites.iter().enumerate() {
-            vertices.push(DeferredVertex {
-                position: [position.0, position.1, position.2],
-                albedo_coords: [
-                region.albedo.x as f32 / region.texture.width() as f32,
-                (region.albedo.y + region.albedo.height) as f32
-                / region.texture.height() as f32,
-                ],
-                normal_coords: [
-                region.normal.x as f32 / region.texture.width() as f32,
-                (region.normal.y + region.normal.height) as f32
-                / region.texture.height() as f32,
-                ],
-            });
-            vertices.push(DeferredVertex {
-                position: [
-                position.0 + region.albedo.width as f32,
-                position.1,
-                position.2,
-                ],
-                albedo_coords: [
-                (region.albedo.x + region.albedo.width) as f32 / region.texture.width() as f32,
-                (region.albedo.y + region.albedo.height) as f32
-                / region.texture.height() as f32,
-                ],
-                normal_coords: [
-                (region.normal.x + region.normal.width) as f32 / region.texture.width() as f32,
-                (region.normal.y + region.normal.height) as f32
-                / region.texture.height() as f32,
-                ],
-            });
-            vertices.push(DeferredVertex {
-                position: [
-                position.0 + region.albedo.width as f32,
-                position.1 + region.albedo.height as f32,
-                position.2,
-                ],
-                albedo_coords: [
-                (region.albedo.x + region.albedo.width) as f32 / region.texture.width() as f32,
-                region.albedo.y as f32 / region.texture.height() as f32,
-                ],
-                normal_coords: [
-                (region.normal.x + region.normal.width) as f32 / region.texture.width() as f32,
-                region.normal.y as f32 / region.texture.height() as f32,
-                ],
-            });
-            vertices.push(DeferredVertex {
-                position: [
-                position.0,
-                position.1 + region.albedo.height as f32,
-                position.2,
-                ],
-                albedo_coords: [
-                region.albedo.x as f32 / region.texture.width() as f32,
-                region.albedo.y as f32 / region.texture.height() as f32,
-                ],
-                normal_coords: [
-                region.normal.x as f32 / region.texture.width() as f32,
-                region.normal.y as f32 / region.texture.height() as f32,
-                ],
+        let (sheet_width, sheet_height) = (sheet.width() as f32, sheet.height() as f32);
+        let mut index = 0;
+        for (position, sprite) in self.sprites.iter() {
+            let albedo = sprite.albedo;
+            // flat sprites still need normal coordinates, but the shader ignores them when flat_normal.w is set
+            let (normal, flat_normal) = match sprite.normal {
+                SpriteNormal::Map(normal) => (normal, [0.0; 4]),
+                SpriteNormal::Flat(flat) => (albedo, [flat[0], flat[1], flat[2], 1.0]),
+            };
+            let (sin, cos) = sprite.transform.rotation.sin_cos();
+            let (scale_x, scale_y) = sprite.transform.scale;
+            // normals are turned with the sprite and mirrored when it's flipped, as columns of a 2x2 matrix
+            let (flip_x, flip_y) = (scale_x.signum(), scale_y.signum());
+            let normal_rotation = [cos * flip_x, sin * flip_x, -sin * flip_y, cos * flip_y];
+            let corners = [(0, 1), (1, 1), (1, 0), (0, 0)].map(|(right, bottom)| {
+                let x = (right as f32 * sprite.size.0 - sprite.pivot.0) * scale_x;
+                let y = ((1 - bottom) as f32 * sprite.size.1 - sprite.pivot.1) * scale_y;
+                ((right, bottom), (position.0 + x * cos - y * sin, position.1 + x * sin + y * cos))
             });
+            let min = corners.iter().fold((f32::MAX, f32::MAX), |min, (_, (x, y))| (min.0.min(*x), min.1.min(*y)));
+            let max = corners.iter().fold((f32::MIN, f32::MIN), |max, (_, (x, y))| (max.0.max(*x), max.1.max(*y)));
+            if !view.overlaps_rect(min, max) {
+                continue;
+            }
+            for ((right, bottom), (x, y)) in corners {
+                vertices.push(DeferredVertex {
+                    position: [x, y, position.2],
+                    albedo_coords: [
+                        (albedo.x + right * albedo.width) as f32 / sheet_width,
+                        (albedo.y + bottom * albedo.height) as f32 / sheet_height,
+                    ],
+                    normal_coords: [
+                        (normal.x + right * normal.width) as f32 / sheet_width,
+                        (normal.y + bottom * normal.height) as f32 / sheet_height,
+                    ],
+                    flat_normal,
+                    tint: sprite.tint,
+                    normal_rotation,
+                });
+            }
             indices.extend_from_slice(&[
                 (4 * index).try_into().unwrap(),
                 (1 + 4 * index).try_into().unwrap(),
@@ -250,6 +373,7 @@ impl DeferredSpriteBatch {
                 (3 + 4 * index).try_into().unwrap(),
                 (4 * index).try_into().unwrap(),
             ]);
+            index += 1;
         }
         (vertices, indices)
     }
@@ -261,4 +385,7 @@ struct DeferredVertex {
     position: [f32; 3],
     albedo_coords: [f32; 2],
     normal_coords: [f32; 2],
+    flat_normal: [f32; 4],
+    tint: [f32; 4],
+    normal_rotation: [f32; 4],
 }
\ No newline at end of file
@@ -1,11 +1,12 @@
-use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BlendComponent, BlendState, Buffer, ColorTargetState, ColorWrites, CommandEncoder, Device, RenderPassDescriptor};
+use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BindGroupLayout, BlendComponent, BlendState, Buffer, ColorTargetState, ColorWrites, CommandEncoder, Device, RenderPassDescriptor};
 
-use crate::{camera::{VIEWPORT_HEIGHT, VIEWPORT_WIDTH}, gfx::pipeline::Pipeline, texture::Texture, Vertex};
+use crate::{camera::{CullStats, ViewBounds}, gfx::{graph::{NodeContext, RenderNode, ALBEDO, LIT, NORMAL}, pipeline::Pipeline}, texture::Texture, Vertex};
 
 pub struct LightingPass {
     pipeline: Pipeline,
-    pub output: Texture,
     lights: Lights,
+    // how many lights were culled in the last frame
+    pub stats: CullStats,
     g_buffer_bind_group_layout: BindGroupLayout,
 
     global_light_pipeline: Pipeline,
@@ -110,17 +111,23 @@ impl LightingPass {
 
         LightingPass {
             pipeline,
-            output: Texture::create_texture(device, Some("LightingPass output"), (VIEWPORT_WIDTH as u32, VIEWPORT_HEIGHT as u32), wgpu::TextureFormat::Rgba8UnormSrgb).unwrap(),
             lights: Lights::new(),
+            stats: CullStats::default(),
             g_buffer_bind_group_layout,
             global_light_vertex_buffer,
             global_light_index_buffer,
             global_light_pipeline,
         }
     }
-    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, camera_bind_group: &BindGroup, albedo_buffer: &Texture, normal_buffer: &Texture) {
+    // lights whose radius doesn't reach into view are skipped
+    // g_buffer_bind_group has the albedo and normal buffers, followed by a sampler
+    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, camera_bind_group: &BindGroup, g_buffer_bind_group: &BindGroup, output: &Texture, view: &ViewBounds) -> CullStats {
 
-        let (vertices, indices) = self.lights.gen_vecs();
+        let (vertices, indices) = self.lights.gen_vecs(view);
+        let stats = CullStats {
+            submitted: self.lights.lights.len(),
+            drawn: vertices.len() / 4,
+        };
         let vertex_buffer = device
         .create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -135,29 +142,11 @@ impl LightingPass {
             usage: wgpu::BufferUsages::INDEX,
         });
         println!("{:?} {:?}", vertices, indices);
-        let g_buffer_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("g_buffer_bind_group"),
-            layout: &self.g_buffer_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&albedo_buffer.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&normal_buffer.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&albedo_buffer.sampler),
-                },
-            ],
-        });
         let mut lighting_pass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("lighting_pass"),
             color_attachments: &[
                 Some(wgpu::RenderPassColorAttachment {
-                    view: &self.output.view,
+                    view: &output.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -175,24 +164,47 @@ impl LightingPass {
             occlusion_query_set: None,
         });
         lighting_pass.set_pipeline(&self.pipeline.pipeline);
-        lighting_pass.set_bind_group(0, &g_buffer_bind_group, &[]);
+        lighting_pass.set_bind_group(0, g_buffer_bind_group, &[]);
         lighting_pass.set_bind_group(1, camera_bind_group, &[]);
         lighting_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
         lighting_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         lighting_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
 
         lighting_pass.set_pipeline(&self.global_light_pipeline.pipeline);
-        lighting_pass.set_bind_group(0, &g_buffer_bind_group, &[]);
+        lighting_pass.set_bind_group(0, g_buffer_bind_group, &[]);
         lighting_pass.set_vertex_buffer(0, self.global_light_vertex_buffer.slice(..));
         lighting_pass.set_index_buffer(self.global_light_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         lighting_pass.draw_indexed(0..6 as u32, 0, 0..1);
         self.lights.lights.clear();
+        stats
+    }
+
+    // forgets every light queued without drawing it
+    pub fn discard(&mut self) {
+        self.lights.lights.clear();
     }
 
     pub fn draw_light(&mut self, light: DynamicLight) {
         self.lights.lights.push(light);
     }
 }
+
+impl RenderNode for LightingPass {
+    fn name(&self) -> &str {
+        "lighting"
+    }
+    fn reads(&self) -> Vec<&'static str> {
+        vec![ALBEDO, NORMAL]
+    }
+    fn writes(&self) -> Vec<&'static str> {
+        vec![LIT]
+    }
+    fn run(&mut self, context: &mut NodeContext) {
+        let g_buffer_bind_group = context.targets.bind_group(context.device, &self.g_buffer_bind_group_layout, &[ALBEDO, NORMAL]);
+        let output = context.targets.texture(LIT);
+        self.stats = self.draw(context.device, context.encoder, context.frame.camera_bind_group, &g_buffer_bind_group, output, &context.frame.view);
+    }
+}
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
 struct GlobalLightVertex {
@@ -240,12 +252,17 @@ impl Lights {
             lights: Vec::new()
         }
     }
-    fn gen_vecs(&self) -> (Vec<LightVertex>, Vec<u16>) {
+    fn gen_vecs(&self, view: &ViewBounds) -> (Vec<LightVertex>, Vec<u16>) {
         let mut vertices = Vec::new();
         let mut indices: Vec<u16> = Vec::new();
         for (index, DynamicLight {
             center, radius, color
-        }) in self.lights.iter().enumerate() {
+        }) in self
+            .lights
+            .iter()
+            .filter(|light| view.overlaps_circle((light.center.0, light.center.1), light.radius))
+            .enumerate()
+        {
             vertices.push(LightVertex {
                 position: [center.0 + radius, center.1 + radius, center.2],
                 center: [center.0, center.1, center.2],
@@ -322,6 +339,14 @@ impl Color {
             b
         }
     }
+    // components are clamped to 0.0..=1.0
+    pub fn from_rgb_f32(r: f32, g: f32, b: f32) -> Color {
+        Color {
+            r: (r.clamp(0.0, 1.0) * 255.0).round() as u8,
+            g: (g.clamp(0.0, 1.0) * 255.0).round() as u8,
+            b: (b.clamp(0.0, 1.0) * 255.0).round() as u8,
+        }
+    }
     pub fn from_hex(code: &'static str) -> Option<Color> {
         let code = code.strip_prefix("#").unwrap_or(code);
         if code.len() != 6 {
@@ -1,3 +1,5 @@
+pub mod debug_pass;
 pub mod deferred_pass;
 pub mod lighting_pass;
+pub mod overlay_pass;
 pub mod present_pass;
\ No newline at end of file
@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BindGroupDescriptor, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrites, CommandEncoder, Device, RenderPassDescriptor};
+
+use crate::{gfx::{graph::{NodeContext, RenderNode, SCENE}, pipeline::Pipeline}, texture::Texture, texture_atlas::TextureRegion, Vertex};
+
+// sprites drawn on top of the lit scene, ignoring lighting entirely
+pub struct OverlayPass {
+    sprites: Vec<OverlaySprite>,
+    texture_bind_group_layout: BindGroupLayout,
+    alpha_pipeline: Pipeline,
+    additive_pipeline: Pipeline,
+    multiply_pipeline: Pipeline,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    // same coordinates as the deferred pass, the position is the bottom left of the sprite
+    World,
+    // internal pixels from the top left of the viewport with y going down, the position is the top left of the sprite
+    Screen,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Alpha,
+    Additive,
+    Multiply,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayOptions {
+    pub space: Space,
+    pub blend: BlendMode,
+    // higher layers are drawn on top, sprites on the same layer are drawn in the order they were submitted
+    pub layer: i32,
+    pub tint: [f32; 4],
+}
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        OverlayOptions {
+            space: Space::World,
+            blend: BlendMode::Alpha,
+            layer: 0,
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+struct OverlaySprite {
+    position: (f32, f32),
+    size: (f32, f32),
+    image: TextureRegion,
+    options: OverlayOptions,
+}
+
+impl OverlayPass {
+    pub fn new(device: &Device, format: wgpu::TextureFormat) -> OverlayPass {
+        let texture_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            ],
+            label: Some("overlay_texture_bind_group_layout"),
+        });
+        let camera_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("camera_bind_group_layout"),
+        });
+        // the shader outputs premultiplied alpha, and the target's alpha is left alone
+        let keep_alpha = BlendComponent {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+        let pipeline = |color: BlendComponent, name| {
+            Pipeline::new::<OverlayVertex>(device, include_wgsl!("../../overlay.wgsl"), &[
+                &texture_bind_group_layout,
+                &camera_bind_group_layout,
+            ], &[Some(ColorTargetState {
+                format,
+                blend: Some(BlendState {
+                    color,
+                    alpha: keep_alpha,
+                }),
+                write_mask: ColorWrites::ALL,
+            })], name)
+        };
+        let alpha_pipeline = pipeline(BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        }, "OverlayPass alpha");
+        let additive_pipeline = pipeline(BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        }, "OverlayPass additive");
+        let multiply_pipeline = pipeline(BlendComponent {
+            src_factor: BlendFactor::Dst,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        }, "OverlayPass multiply");
+
+        OverlayPass {
+            sprites: Vec::new(),
+            texture_bind_group_layout,
+            alpha_pipeline,
+            additive_pipeline,
+            multiply_pipeline,
+        }
+    }
+
+    // the sprite's pivot is placed at position
+    pub fn draw_sprite(&mut self, position: (f32, f32), image: TextureRegion, options: OverlayOptions) {
+        let size = (image.src.width as f32, image.src.height as f32);
+        let (pivot_x, pivot_y) = image.pivot;
+        let corner = match options.space {
+            Space::World => (position.0 - pivot_x, position.1 - pivot_y),
+            Space::Screen => (position.0 - pivot_x, position.1 - (size.1 - pivot_y)),
+        };
+        self.draw_sprite_sized(corner, size, image, options);
+    }
+    // sized sprites ignore their pivot, position is always the bottom left in world space and the top left in screen space
+    pub fn draw_sprite_sized(&mut self, position: (f32, f32), size: (f32, f32), image: TextureRegion, options: OverlayOptions) {
+        self.sprites.push(OverlaySprite {
+            position,
+            size,
+            image,
+            options,
+        });
+    }
+
+    // composites the queued sprites onto the target, which is expected to already contain the lit scene
+    pub fn draw(&mut self, device: &Device, encoder: &mut CommandEncoder, camera_bind_group: &BindGroup, target: &Texture) {
+        if self.sprites.is_empty() {
+            return;
+        }
+        // sort_by_key is stable, so submission order is kept within a layer
+        self.sprites.sort_by_key(|sprite| sprite.options.layer);
+
+        let mut vertices = Vec::new();
+        let mut indices: Vec<u16> = Vec::new();
+        // consecutive sprites that share a texture and blend mode are drawn together
+        let mut batches: Vec<(Arc<Texture>, BlendMode, std::ops::Range<u32>)> = Vec::new();
+        for (index, sprite) in self.sprites.iter().enumerate() {
+            vertices.extend(sprite.gen_vertices());
+            indices.extend_from_slice(&[
+                (4 * index).try_into().unwrap(),
+                (1 + 4 * index).try_into().unwrap(),
+                (2 + 4 * index).try_into().unwrap(),
+                (2 + 4 * index).try_into().unwrap(),
+                (3 + 4 * index).try_into().unwrap(),
+                (4 * index).try_into().unwrap(),
+            ]);
+            let end = indices.len() as u32;
+            match batches.last_mut() {
+                Some((texture, blend, range)) if *texture == sprite.image.texture && *blend == sprite.options.blend => {
+                    range.end = end;
+                }
+                _ => batches.push((sprite.image.texture.clone(), sprite.options.blend, end - 6..end)),
+            }
+        }
+
+        let vertex_buffer = device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices.as_slice()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Index Buffer"),
+            contents: bytemuck::cast_slice(indices.as_slice()),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let bind_groups: Vec<BindGroup> = batches.iter().map(|(texture, _, _)| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("overlay_texture_bind_group"),
+                layout: &self.texture_bind_group_layout,
+                entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                ],
+            })
+        }).collect();
+
+        let mut overlay_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("overlay_pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+                ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        overlay_pass.set_bind_group(1, camera_bind_group, &[]);
+        overlay_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        overlay_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        for ((_, blend, range), bind_group) in batches.into_iter().zip(bind_groups.iter()) {
+            let pipeline = match blend {
+                BlendMode::Alpha => &self.alpha_pipeline,
+                BlendMode::Additive => &self.additive_pipeline,
+                BlendMode::Multiply => &self.multiply_pipeline,
+            };
+            overlay_pass.set_pipeline(&pipeline.pipeline);
+            overlay_pass.set_bind_group(0, bind_group, &[]);
+            overlay_pass.draw_indexed(range, 0, 0..1);
+        }
+        std::mem::drop(overlay_pass);
+        self.sprites.clear();
+    }
+
+    // forgets everything queued without drawing it
+    pub fn discard(&mut self) {
+        self.sprites.clear();
+    }
+}
+
+impl RenderNode for OverlayPass {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+    fn reads(&self) -> Vec<&'static str> {
+        vec![SCENE]
+    }
+    fn writes(&self) -> Vec<&'static str> {
+        vec![SCENE]
+    }
+    fn run(&mut self, context: &mut NodeContext) {
+        self.draw(context.device, context.encoder, context.frame.camera_bind_group, context.targets.texture(SCENE));
+    }
+}
+
+impl OverlaySprite {
+    fn gen_vertices(&self) -> [OverlayVertex; 4] {
+        let src = self.image.src;
+        let (texture_width, texture_height) = (self.image.texture.width() as f32, self.image.texture.height() as f32);
+        let (width, height) = self.size;
+        let (x, y) = self.position;
+        let screen_space = match self.options.space {
+            Space::World => 0.0,
+            Space::Screen => 1.0,
+        };
+        // the bottom of the sprite is +height in screen space, since y goes down
+        let bottom = match self.options.space {
+            Space::World => y,
+            Space::Screen => y + height,
+        };
+        let top = match self.options.space {
+            Space::World => y + height,
+            Space::Screen => y,
+        };
+        let left_u = src.x as f32 / texture_width;
+        let right_u = (src.x + src.width) as f32 / texture_width;
+        let top_v = src.y as f32 / texture_height;
+        let bottom_v = (src.y + src.height) as f32 / texture_height;
+        let vertex = |position: [f32; 2], tex_coords: [f32; 2]| OverlayVertex {
+            position: [position[0], position[1], 0.0],
+            tex_coords,
+            tint: self.options.tint,
+            screen_space,
+        };
+        [
+            vertex([x, bottom], [left_u, bottom_v]),
+            vertex([x + width, bottom], [right_u, bottom_v]),
+            vertex([x + width, top], [right_u, top_v]),
+            vertex([x, top], [left_u, top_v]),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+struct OverlayVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+    tint: [f32; 4],
+    screen_space: f32,
+}
@@ -1,6 +1,5 @@
-use std::{iter, sync::Arc};
-
-use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, Buffer, Color, ColorTargetState, ColorWrites, CommandEncoder, Device, Queue, Surface, TextureFormat, TextureView};
+use serde::{Deserialize, Serialize};
+use wgpu::{include_wgsl, util::DeviceExt, BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, Buffer, Color, ColorTargetState, ColorWrites, CommandEncoder, Device, TextureFormat, TextureView};
 
 use crate::{gfx::pipeline::Pipeline, texture::Texture, Vertex};
 
@@ -30,7 +29,106 @@ PresentVertex {
 },
 ];
 const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewportUniform {
+    viewport: [[f32; 4]; 4],
+    // added to the texture coordinates, only x and y are used
+    texel_offset: [f32; 4],
+    // the scale along x and y, then 1.0 if sharp bilinear filtering is on, then 1.0 if the crt filter is on
+    filtering: [f32; 4],
+    // scanlines, mask, curvature and fringing from CrtFilter
+    crt: [f32; 4],
+}
+
+// makes the upscaled image look like it's on an old crt. it runs on every window pixel, so it looks the same at any scale
+#[derive(Clone, Copy, Debug)]
+pub struct CrtFilter {
+    // how dark the gaps between the internal resolution's rows get, from 0 to 1
+    pub scanlines: f32,
+    // how much the red, green and blue stripes of the aperture grille darken the other two channels, from 0 to 1
+    pub mask: f32,
+    // how much the image bulges, 0 is flat
+    pub curvature: f32,
+    // how many internal pixels red and blue are pulled apart at the edges of the screen
+    pub fringing: f32,
+}
+
+impl Default for CrtFilter {
+    fn default() -> Self {
+        CrtFilter {
+            scanlines: 0.4,
+            mask: 0.2,
+            curvature: 0.04,
+            fringing: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScaleMode {
+    // the largest whole number scale that fits, with black bars around the rest, so every internal pixel is the same size
+    Integer,
+    // keeps the aspect ratio and fills as much of the window as it can
+    #[default]
+    Fit,
+    // fills the window, ignoring the aspect ratio
+    Stretch,
+    // keeps the aspect ratio and fills the whole window, cutting off whatever doesn't fit
+    Crop,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 4] = [ScaleMode::Integer, ScaleMode::Fit, ScaleMode::Stretch, ScaleMode::Crop];
+
+    pub fn next(self) -> ScaleMode {
+        let index = ScaleMode::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+        ScaleMode::ALL[(index + 1) % ScaleMode::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    // places the internal image in the window, in clip space
+    pub matrix: [[f32; 4]; 4],
+    // window pixels per internal pixel along each axis
+    pub scale: (f32, f32),
+}
+
+impl Viewport {
+    pub fn new(mode: ScaleMode, window: (u32, u32), internal: (f32, f32)) -> Viewport {
+        let (width, height) = (window.0 as f32, window.1 as f32);
+        let (ratio_x, ratio_y) = (width / internal.0, height / internal.1);
+        let scale = match mode {
+            ScaleMode::Integer => {
+                let scale = ratio_x.min(ratio_y).floor().max(1.0);
+                (scale, scale)
+            }
+            ScaleMode::Fit => (ratio_x.min(ratio_y), ratio_x.min(ratio_y)),
+            ScaleMode::Stretch => (ratio_x, ratio_y),
+            ScaleMode::Crop => (ratio_x.max(ratio_y), ratio_x.max(ratio_y)),
+        };
+        let size = (internal.0 * scale.0, internal.1 * scale.1);
+        // the image starts on a whole pixel, so whole number scales line up with the window's pixels
+        let left = ((width - size.0) / 2.0).floor();
+        let top = ((height - size.1) / 2.0).floor();
+        let matrix = cgmath::Matrix4::from_translation(
+            ((2.0 * left + size.0) / width - 1.0, 1.0 - (2.0 * top + size.1) / height, 0.0).into(),
+        ) * cgmath::Matrix4::from_nonuniform_scale(size.0 / width, size.1 / height, 1.0);
+        Viewport {
+            matrix: matrix.into(),
+            scale,
+        }
+    }
+}
+
 pub struct PresentPass {
+    // smooths the edges between internal pixels when they don't all cover the same number of window pixels,
+    // while keeping them sharp everywhere else
+    pub sharp_bilinear: bool,
+    pub crt: Option<CrtFilter>,
+    linear_sampler: wgpu::Sampler,
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     viewport_bind_group_layout: BindGroupLayout,
@@ -54,7 +152,7 @@ impl PresentPass {
         let viewport_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -64,6 +162,16 @@ impl PresentPass {
             }],
             label: Some("viewport_bind_group_layout"),
         });
+        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("PresentPass linear sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
 
         
         let texture_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -95,6 +203,9 @@ impl PresentPass {
 
         )], "PresentPass");
         PresentPass {
+            sharp_bilinear: false,
+            crt: None,
+            linear_sampler,
             vertex_buffer,
             index_buffer,
             viewport_bind_group_layout,
@@ -102,12 +213,34 @@ impl PresentPass {
             pipeline
         }
     }
-    pub fn present(&self, device: &Device, mut encoder: CommandEncoder, queue: &Queue, to_present: &Texture, viewport_matrix: [[f32; 4]; 4], surface: &Surface) {
-        let output_texture = surface.get_current_texture().unwrap();
-        let output_view = output_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    // draws the upscaled image into any target with the presented format, like an offscreen copy of the window for screenshots.
+    // subpixel_offset is how far past the rendered position the camera really is, in internal pixels.
+    // the image is shifted by it in screen pixels, so scrolling stays smooth when each internal pixel covers several,
+    // at the cost of the last row or column on the leading edge being stretched by up to a pixel
+    pub fn draw(&self, device: &Device, encoder: &mut CommandEncoder, to_present: &Texture, viewport: &Viewport, subpixel_offset: (f32, f32), output_view: &TextureView) {
+        // texture coordinates go down, so moving the camera up moves them up too
+        let uniform = ViewportUniform {
+            viewport: viewport.matrix,
+            texel_offset: [
+                subpixel_offset.0 / to_present.width() as f32,
+                -subpixel_offset.1 / to_present.height() as f32,
+                0.0,
+                0.0,
+            ],
+            filtering: [
+                viewport.scale.0,
+                viewport.scale.1,
+                if self.sharp_bilinear { 1.0 } else { 0.0 },
+                if self.crt.is_some() { 1.0 } else { 0.0 },
+            ],
+            crt: self
+                .crt
+                .map(|crt| [crt.scanlines, crt.mask, crt.curvature, crt.fringing])
+                .unwrap_or_default(),
+        };
         let viewport_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("viewport Buffer"),
-            contents: bytemuck::cast_slice(&[viewport_matrix]),
+            contents: bytemuck::cast_slice(&[uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
                 
@@ -129,7 +262,11 @@ impl PresentPass {
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::Sampler(&to_present.sampler),
+                resource: wgpu::BindingResource::Sampler(if self.sharp_bilinear {
+                    &self.linear_sampler
+                } else {
+                    &to_present.sampler
+                }),
             },
             ],
         });
@@ -137,7 +274,7 @@ impl PresentPass {
         let mut present_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Present Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &output_view,
+                view: output_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(Color {
@@ -160,11 +297,6 @@ impl PresentPass {
         present_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         present_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         present_pass.draw_indexed(0..6, 0, 0..1);
-
-        std::mem::drop(present_pass);
-
-        queue.submit(iter::once(encoder.finish()));
-        output_texture.present();
     }
 }
 
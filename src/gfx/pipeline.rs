@@ -9,6 +9,9 @@ pub struct Pipeline {
 impl Pipeline {
     // use wgpu::include_wgsl!("shader.wgsl")
     pub fn new<V: Vertex>(device: &Device, shader: ShaderModuleDescriptor, bind_group_layouts: &[&BindGroupLayout], targets: &[Option<ColorTargetState>], name: &'static str) -> Pipeline {
+        Pipeline::with_topology::<V>(device, shader, bind_group_layouts, targets, wgpu::PrimitiveTopology::TriangleList, name)
+    }
+    pub fn with_topology<V: Vertex>(device: &Device, shader: ShaderModuleDescriptor, bind_group_layouts: &[&BindGroupLayout], targets: &[Option<ColorTargetState>], topology: wgpu::PrimitiveTopology, name: &'static str) -> Pipeline {
         let shader = device.create_shader_module(shader);
         let pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -33,10 +36,11 @@ impl Pipeline {
                 targets,
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                topology,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                // everything is drawn as flat quads, and flipped sprites wind the other way, so nothing is culled
+                cull_mode: None,
                 polygon_mode: wgpu::PolygonMode::Fill,
                 unclipped_depth: false,
                 conservative: false,
@@ -0,0 +1,213 @@
+use std::any::Any;
+
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, ColorTargetState,
+    ColorWrites, CommandEncoder, Device, Sampler,
+};
+
+use super::{texture_bind_group_layout, uniform_bind_group, PostEffect, PostFrame, PostVertex, POST_FORMAT};
+use crate::{gfx::pipeline::Pipeline, texture::Texture};
+
+/*
+   Bloom picks out the pixels brighter than the threshold, blurs them by halving them down a chain of smaller textures and adding
+   them back up again, then adds the blur on top of the untouched scene. The scene itself is never filtered, so the pixel art
+   stays sharp and only the glow is soft.
+*/
+
+// the smallest level is 1/32 of the internal resolution, which is about as far as glow should reach at 320x180
+const MAX_LEVELS: u32 = 5;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    // threshold, intensity, then padding
+    params: [f32; 4],
+}
+
+pub struct Bloom {
+    // how much of the blur is added to the scene
+    pub intensity: f32,
+    // how bright a pixel's brightest channel has to be before it glows, from 0 to 1
+    pub threshold: f32,
+    // how many levels of the chain are blurred together, from 1 to 5. each level roughly doubles how far the glow spreads
+    pub radius: u32,
+    threshold_pipeline: Pipeline,
+    downsample_pipeline: Pipeline,
+    upsample_pipeline: Pipeline,
+    composite_pipeline: Pipeline,
+    texture_layout: BindGroupLayout,
+    // the blur has to be filtered, unlike everything else
+    linear_sampler: Sampler,
+    uniform_buffer: Buffer,
+    uniform_bind_group: BindGroup,
+    // half the input size and smaller, made on the first frame and whenever the input size changes
+    levels: Vec<Texture>,
+    level_bind_groups: Vec<BindGroup>,
+}
+
+impl Bloom {
+    pub fn new(device: &Device) -> Bloom {
+        let texture_layout = texture_bind_group_layout(device);
+        let (uniform_layout, uniform_buffer, uniform_bind_group) =
+            uniform_bind_group(device, std::mem::size_of::<BloomUniform>() as u64, "bloom_uniform");
+        let target = |blend| {
+            [Some(ColorTargetState {
+                format: POST_FORMAT,
+                blend,
+                write_mask: ColorWrites::ALL,
+            })]
+        };
+        let add = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+        let threshold_pipeline = Pipeline::new::<PostVertex>(
+            device,
+            include_wgsl!("../../bloom_threshold.wgsl"),
+            &[&texture_layout, &uniform_layout],
+            &target(None),
+            "Bloom threshold",
+        );
+        let downsample_pipeline = Pipeline::new::<PostVertex>(
+            device,
+            include_wgsl!("../../bloom_downsample.wgsl"),
+            &[&texture_layout],
+            &target(None),
+            "Bloom downsample",
+        );
+        let upsample_pipeline = Pipeline::new::<PostVertex>(
+            device,
+            include_wgsl!("../../bloom_upsample.wgsl"),
+            &[&texture_layout],
+            &target(Some(BlendState { color: add, alpha: add })),
+            "Bloom upsample",
+        );
+        let composite_pipeline = Pipeline::new::<PostVertex>(
+            device,
+            include_wgsl!("../../bloom_composite.wgsl"),
+            &[&texture_layout, &texture_layout, &uniform_layout],
+            &target(None),
+            "Bloom composite",
+        );
+        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Bloom {
+            intensity: 0.6,
+            threshold: 0.75,
+            radius: 3,
+            threshold_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            texture_layout,
+            linear_sampler,
+            uniform_buffer,
+            uniform_bind_group,
+            levels: Vec::new(),
+            level_bind_groups: Vec::new(),
+        }
+    }
+
+    fn linear_bind_group(&self, device: &Device, texture: &Texture) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bloom_texture_bind_group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.linear_sampler),
+                },
+            ],
+        })
+    }
+
+    fn resize_levels(&mut self, device: &Device, size: (u32, u32)) {
+        let first = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+        if self.levels.first().is_some_and(|level| (level.width(), level.height()) == first) {
+            return;
+        }
+        self.levels = (0..MAX_LEVELS)
+            .map(|level| {
+                let size = ((size.0 >> (level + 1)).max(1), (size.1 >> (level + 1)).max(1));
+                Texture::create_texture(device, Some("bloom_level"), size, POST_FORMAT).unwrap()
+            })
+            .collect();
+        self.level_bind_groups = self.levels.iter().map(|level| self.linear_bind_group(device, level)).collect();
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+    fn apply(&mut self, frame: &PostFrame, encoder: &mut CommandEncoder, input: &Texture, output: &Texture) {
+        self.resize_levels(frame.device, (input.width(), input.height()));
+        let levels = self.radius.clamp(1, MAX_LEVELS) as usize;
+        frame.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[BloomUniform {
+                params: [self.threshold, self.intensity, 0.0, 0.0],
+            }]),
+        );
+        let input_bind_group = self.linear_bind_group(frame.device, input);
+
+        // bright pixels only, at half size
+        {
+            let mut pass = frame.quad.begin(encoder, &self.levels[0].view, "bloom_threshold_pass");
+            pass.set_pipeline(&self.threshold_pipeline.pipeline);
+            pass.set_bind_group(0, &input_bind_group, &[]);
+            pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            frame.quad.draw(&mut pass);
+        }
+        for level in 1..levels {
+            let mut pass = frame.quad.begin(encoder, &self.levels[level].view, "bloom_downsample_pass");
+            pass.set_pipeline(&self.downsample_pipeline.pipeline);
+            pass.set_bind_group(0, &self.level_bind_groups[level - 1], &[]);
+            frame.quad.draw(&mut pass);
+        }
+        // each level is blurred and added onto the next biggest one, so the first level ends up with all of them
+        for level in (1..levels).rev() {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("bloom_upsample_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.levels[level - 1].view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.upsample_pipeline.pipeline);
+            pass.set_bind_group(0, &self.level_bind_groups[level], &[]);
+            frame.quad.draw(&mut pass);
+        }
+
+        let scene_bind_group = frame.quad.texture_bind_group(frame.device, input);
+        let mut pass = frame.quad.begin(encoder, &output.view, "bloom_composite_pass");
+        pass.set_pipeline(&self.composite_pipeline.pipeline);
+        pass.set_bind_group(0, &scene_bind_group, &[]);
+        pass.set_bind_group(1, &self.level_bind_groups[0], &[]);
+        pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+        frame.quad.draw(&mut pass);
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
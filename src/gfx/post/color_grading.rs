@@ -0,0 +1,292 @@
+use std::{any::Any, path::Path};
+
+use anyhow::*;
+use image::GenericImageView;
+use wgpu::{include_wgsl, BindGroup, BindGroupLayout, Buffer, ColorTargetState, ColorWrites, CommandEncoder, Device, Queue, Sampler};
+
+use super::{uniform_bind_group, PostEffect, PostFrame, PostVertex, POST_FORMAT};
+use crate::{gfx::pipeline::Pipeline, texture::Texture};
+
+/*
+   Color grading remaps every color of the scene through a lookup table, authored by running the neutral strip through the same
+   adjustments in an image editor as a screenshot of the game.
+
+   The strips are square slices laid out left to right, 16 slices of 16x16 for a 256x16 image or 32 of 32x32 for 1024x32.
+   Red goes left to right within a slice, green top to bottom, and blue from slice to slice.
+*/
+
+// a lookup table as a 3D texture, so the GPU interpolates between entries along all three axes
+pub struct Lut {
+    view: wgpu::TextureView,
+    size: u32,
+}
+
+#[allow(dead_code)]
+impl Lut {
+    pub fn load<P: AsRef<Path>>(device: &Device, queue: &Queue, path: P) -> Result<Lut> {
+        let path = path.as_ref();
+        let img = image::open(path).with_context(|| format!("couldn't load lut {}", path.display()))?;
+        Lut::from_image(device, queue, &img, &path.to_string_lossy())
+    }
+
+    pub fn from_image(device: &Device, queue: &Queue, img: &image::DynamicImage, label: &str) -> Result<Lut> {
+        let (width, height) = img.dimensions();
+        if height < 2 || width != height * height {
+            bail!("lut {} is {}x{}, it should be a strip like 256x16 or 1024x32", label, width, height);
+        }
+        let size = height;
+        let strip = img.to_rgba8();
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    data.extend_from_slice(&strip.get_pixel(blue * size + red, green).0);
+                }
+            }
+        }
+        Result::Ok(Lut::from_data(device, queue, size, &data, label))
+    }
+
+    // a lut that leaves colors as they are
+    pub fn identity(device: &Device, queue: &Queue, size: u32) -> Lut {
+        let step = |value: u32| (value * 255 / (size - 1)) as u8;
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    data.extend_from_slice(&[step(red), step(green), step(blue), 255]);
+                }
+            }
+        }
+        Lut::from_data(device, queue, size, &data, "identity_lut")
+    }
+
+    // data is rgba8, red changing fastest and blue slowest
+    fn from_data(device: &Device, queue: &Queue, size: u32, data: &[u8], label: &str) -> Lut {
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // the entries are converted from srgb in the shader, so interpolation happens in the same space they were authored in
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size),
+                rows_per_image: Some(size),
+            },
+            extent,
+        );
+        Lut {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            size,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradingUniform {
+    // weight, how far through the fade, then the size of each lut
+    params: [f32; 4],
+}
+
+struct Fade {
+    to: Lut,
+    duration: f32,
+    elapsed: f32,
+}
+
+pub struct ColorGrading {
+    // how much of the grade is applied, 0 leaves the scene as it is
+    pub weight: f32,
+    current: Lut,
+    fade: Option<Fade>,
+    pipeline: Pipeline,
+    lut_layout: BindGroupLayout,
+    lut_sampler: Sampler,
+    // cleared whenever the luts change, and rebuilt on the next frame
+    lut_bind_group: Option<BindGroup>,
+    uniform_buffer: Buffer,
+    uniform_bind_group: BindGroup,
+}
+
+#[allow(dead_code)]
+impl ColorGrading {
+    pub fn new(device: &Device, lut: Lut) -> ColorGrading {
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                lut_layout_entry(0),
+                lut_layout_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("lut_bind_group_layout"),
+        });
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let (uniform_layout, uniform_buffer, uniform_bind_group) =
+            uniform_bind_group(device, std::mem::size_of::<GradingUniform>() as u64, "grading_uniform");
+        let pipeline = Pipeline::new::<PostVertex>(
+            device,
+            include_wgsl!("../../color_grading.wgsl"),
+            &[&super::texture_bind_group_layout(device), &lut_layout, &uniform_layout],
+            &[Some(ColorTargetState {
+                format: POST_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+            "ColorGrading",
+        );
+        ColorGrading {
+            weight: 1.0,
+            current: lut,
+            fade: None,
+            pipeline,
+            lut_layout,
+            lut_sampler,
+            lut_bind_group: None,
+            uniform_buffer,
+            uniform_bind_group,
+        }
+    }
+
+    // switches straight to lut, cancelling any fade
+    pub fn set_lut(&mut self, lut: Lut) {
+        self.current = lut;
+        self.fade = None;
+        self.lut_bind_group = None;
+    }
+
+    // cross fades from the current lut to lut over duration seconds. if a fade is already running, it skips to the end of it first
+    pub fn fade_to(&mut self, lut: Lut, duration: f32) {
+        if let Some(fade) = self.fade.take() {
+            self.current = fade.to;
+        }
+        self.fade = Some(Fade {
+            to: lut,
+            duration,
+            elapsed: 0.0,
+        });
+        self.lut_bind_group = None;
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    // advances the fade, call once a frame
+    pub fn update(&mut self, dt: f32) {
+        let Some(fade) = self.fade.as_mut() else {
+            return;
+        };
+        fade.elapsed += dt;
+        if fade.elapsed >= fade.duration {
+            self.current = self.fade.take().unwrap().to;
+            self.lut_bind_group = None;
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        match &self.fade {
+            Some(fade) if fade.duration > 0.0 => (fade.elapsed / fade.duration).clamp(0.0, 1.0),
+            Some(_) => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+fn lut_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D3,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+fn lut_bind_group(device: &Device, layout: &BindGroupLayout, sampler: &Sampler, from: &Lut, to: &Lut) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("lut_bind_group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&from.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&to.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &str {
+        "color_grading"
+    }
+    fn apply(&mut self, frame: &PostFrame, encoder: &mut CommandEncoder, input: &Texture, output: &Texture) {
+        let to = self.fade.as_ref().map(|fade| &fade.to).unwrap_or(&self.current);
+        frame.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[GradingUniform {
+                params: [self.weight, self.progress(), self.current.size as f32, to.size as f32],
+            }]),
+        );
+        if self.lut_bind_group.is_none() {
+            self.lut_bind_group = Some(lut_bind_group(frame.device, &self.lut_layout, &self.lut_sampler, &self.current, to));
+        }
+        let input_bind_group = frame.quad.texture_bind_group(frame.device, input);
+        let mut pass = frame.quad.begin(encoder, &output.view, "color_grading_pass");
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &input_bind_group, &[]);
+        pass.set_bind_group(1, self.lut_bind_group.as_ref().unwrap(), &[]);
+        pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+        frame.quad.draw(&mut pass);
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
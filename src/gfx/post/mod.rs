@@ -0,0 +1,301 @@
+use std::any::Any;
+
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, Buffer, CommandEncoder, Device, Queue, RenderPass, TextureFormat};
+
+use super::graph::{NodeContext, RenderNode, LIT, SCENE};
+use crate::{texture::Texture, Vertex};
+
+pub mod bloom;
+pub mod color_grading;
+pub mod vignette;
+
+/*
+   Post processing effects run in order on the finished scene at the internal resolution. Each one reads the previous effect's
+   output and writes into whichever of the two ping pong targets that didn't use, so effects never read and write the same texture.
+   The last effect writes straight into the render graph's scene target.
+
+   Effects are found by name to remove, reorder, toggle or change their parameters while the game is running.
+*/
+
+// the format of the lit scene and the ping pong targets
+pub const POST_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+pub trait PostEffect: Any {
+    fn name(&self) -> &str;
+    // has to write every pixel of output
+    fn apply(&mut self, frame: &PostFrame, encoder: &mut CommandEncoder, input: &Texture, output: &Texture);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+// what every effect gets to draw with
+pub struct PostFrame<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub quad: &'a FullscreenQuad,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Vertex)]
+pub struct PostVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+const VERTICES: &[PostVertex] = &[
+    PostVertex {
+        position: [1., 1., 0.],
+        tex_coords: [1., 0.],
+    },
+    PostVertex {
+        position: [-1., 1., 0.],
+        tex_coords: [0., 0.],
+    },
+    PostVertex {
+        position: [-1., -1., 0.],
+        tex_coords: [0., 1.],
+    },
+    PostVertex {
+        position: [1., -1., 0.],
+        tex_coords: [1., 1.],
+    },
+];
+const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
+
+// a quad covering the whole target, along with the bind group layout every effect uses for its input
+pub struct FullscreenQuad {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    pub texture_bind_group_layout: BindGroupLayout,
+}
+
+impl FullscreenQuad {
+    pub fn new(device: &Device) -> FullscreenQuad {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Index Buffer"),
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        FullscreenQuad {
+            vertex_buffer,
+            index_buffer,
+            texture_bind_group_layout: texture_bind_group_layout(device),
+        }
+    }
+
+    pub fn texture_bind_group(&self, device: &Device, texture: &Texture) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post_texture_bind_group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        })
+    }
+
+    // clears the target and starts a pass drawing into it
+    pub fn begin<'a>(&self, encoder: &'a mut CommandEncoder, target: &'a wgpu::TextureView, label: &str) -> RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut RenderPass<'a>) {
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..6, 0, 0..1);
+    }
+}
+
+pub fn texture_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: Some("post_texture_bind_group_layout"),
+    })
+}
+
+// a uniform buffer with its bind group, visible to fragment shaders, for an effect's parameters
+pub fn uniform_bind_group(device: &Device, size: u64, label: &'static str) -> (BindGroupLayout, Buffer, BindGroup) {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+        label: Some(label),
+    });
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+        label: Some(label),
+    });
+    (layout, buffer, bind_group)
+}
+
+struct Entry {
+    effect: Box<dyn PostEffect>,
+    enabled: bool,
+}
+
+pub struct PostStack {
+    effects: Vec<Entry>,
+    // for the effects in between the first and last
+    targets: [Texture; 2],
+    quad: FullscreenQuad,
+}
+
+#[allow(dead_code)]
+impl PostStack {
+    pub fn new(device: &Device, size: (u32, u32)) -> PostStack {
+        PostStack {
+            effects: Vec::new(),
+            targets: [
+                Texture::create_texture(device, Some("post_target_a"), size, POST_FORMAT).unwrap(),
+                Texture::create_texture(device, Some("post_target_b"), size, POST_FORMAT).unwrap(),
+            ],
+            quad: FullscreenQuad::new(device),
+        }
+    }
+
+    // the targets have to match the internal resolution
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        self.targets = [
+            Texture::create_texture(device, Some("post_target_a"), size, POST_FORMAT).unwrap(),
+            Texture::create_texture(device, Some("post_target_b"), size, POST_FORMAT).unwrap(),
+        ];
+    }
+
+    pub fn push(&mut self, effect: Box<dyn PostEffect>) {
+        self.effects.push(Entry { effect, enabled: true });
+    }
+    pub fn insert(&mut self, index: usize, effect: Box<dyn PostEffect>) {
+        self.effects.insert(index.min(self.effects.len()), Entry { effect, enabled: true });
+    }
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let index = self.index_of(name)?;
+        Some(self.effects.remove(index).effect)
+    }
+    // returns false if there's no effect called name
+    pub fn move_to(&mut self, name: &str, index: usize) -> bool {
+        let Some(from) = self.index_of(name) else {
+            return false;
+        };
+        let entry = self.effects.remove(from);
+        self.effects.insert(index.min(self.effects.len()), entry);
+        true
+    }
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let Some(index) = self.index_of(name) else {
+            return false;
+        };
+        self.effects[index].enabled = enabled;
+        true
+    }
+    pub fn names(&self) -> Vec<&str> {
+        self.effects.iter().map(|entry| entry.effect.name()).collect()
+    }
+    // None if there's no effect called name, or it isn't a T
+    pub fn get_mut<T: PostEffect>(&mut self, name: &str) -> Option<&mut T> {
+        let index = self.index_of(name)?;
+        self.effects[index].effect.as_any_mut().downcast_mut::<T>()
+    }
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.effects.iter().position(|entry| entry.effect.name() == name)
+    }
+
+    // runs every enabled effect, the last one writing into output. input is copied straight into output if none are enabled
+    pub fn apply(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, input: &Texture, output: &Texture) {
+        let frame = PostFrame {
+            device,
+            queue,
+            quad: &self.quad,
+        };
+        let enabled = self.effects.iter().filter(|entry| entry.enabled).count();
+        if enabled == 0 {
+            encoder.copy_texture_to_texture(input.texture.as_image_copy(), output.texture.as_image_copy(), input.texture.size());
+            return;
+        }
+        let mut current = None;
+        for (index, entry) in self.effects.iter_mut().filter(|entry| entry.enabled).enumerate() {
+            let source = match current {
+                None => input,
+                Some(target) => &self.targets[target],
+            };
+            if index + 1 == enabled {
+                entry.effect.apply(&frame, encoder, source, output);
+            } else {
+                let target = current.map_or(0, |target| 1 - target);
+                entry.effect.apply(&frame, encoder, source, &self.targets[target]);
+                current = Some(target);
+            }
+        }
+    }
+}
+
+impl RenderNode for PostStack {
+    fn name(&self) -> &str {
+        "post"
+    }
+    fn reads(&self) -> Vec<&'static str> {
+        vec![LIT]
+    }
+    fn writes(&self) -> Vec<&'static str> {
+        vec![SCENE]
+    }
+    fn run(&mut self, context: &mut NodeContext) {
+        let (input, output) = (context.targets.texture(LIT), context.targets.texture(SCENE));
+        self.apply(context.device, context.queue, context.encoder, input, output);
+    }
+}
@@ -0,0 +1,85 @@
+use std::any::Any;
+
+use wgpu::{include_wgsl, BindGroup, Buffer, ColorTargetState, ColorWrites, CommandEncoder, Device};
+
+use super::{uniform_bind_group, PostEffect, PostFrame, PostVertex, POST_FORMAT};
+use crate::{gfx::pipeline::Pipeline, texture::Texture};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniform {
+    color: [f32; 4],
+    // intensity, radius, softness, then the aspect ratio of the target
+    shape: [f32; 4],
+}
+
+// darkens the edges of the screen towards color
+pub struct Vignette {
+    pub color: [f32; 3],
+    // how dark the corners get, from 0 to 1
+    pub intensity: f32,
+    // how far out the darkening starts, as a fraction of the distance from the centre to a corner
+    pub radius: f32,
+    // how far past radius it takes to reach full intensity
+    pub softness: f32,
+    pipeline: Pipeline,
+    uniform_buffer: Buffer,
+    uniform_bind_group: BindGroup,
+}
+
+impl Vignette {
+    pub fn new(device: &Device) -> Vignette {
+        let (uniform_layout, uniform_buffer, uniform_bind_group) =
+            uniform_bind_group(device, std::mem::size_of::<VignetteUniform>() as u64, "vignette_uniform");
+        let pipeline = Pipeline::new::<PostVertex>(
+            device,
+            include_wgsl!("../../vignette.wgsl"),
+            &[&super::texture_bind_group_layout(device), &uniform_layout],
+            &[Some(ColorTargetState {
+                format: POST_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+            "Vignette",
+        );
+        Vignette {
+            color: [0.0, 0.0, 0.0],
+            intensity: 0.5,
+            radius: 0.6,
+            softness: 0.4,
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &str {
+        "vignette"
+    }
+    fn apply(&mut self, frame: &PostFrame, encoder: &mut CommandEncoder, input: &Texture, output: &Texture) {
+        frame.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[VignetteUniform {
+                color: [self.color[0], self.color[1], self.color[2], 1.0],
+                shape: [
+                    self.intensity,
+                    self.radius,
+                    self.softness.max(0.001),
+                    input.width() as f32 / input.height() as f32,
+                ],
+            }]),
+        );
+        let input_bind_group = frame.quad.texture_bind_group(frame.device, input);
+        let mut pass = frame.quad.begin(encoder, &output.view, "vignette_pass");
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &input_bind_group, &[]);
+        pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        frame.quad.draw(&mut pass);
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use anyhow::*;
+use image::RgbaImage;
+use wgpu::{Buffer, CommandEncoder, Device};
+
+use super::capture::{PendingReadback, Readback};
+
+/*
+   Recording saves rendered frames as a numbered png sequence, without stalling the game while encoding keeps up. Each captured frame is copied into
+   a buffer, mapped once the GPU gets to it, and handed to a thread that does the slow part of encoding and writing it.
+
+   Buffers are reused once their frame has been read, and only so many frames can be waiting on the GPU or the writer.
+   If encoding falls behind, the game stalls until it catches up instead of holding every frame in memory.
+*/
+
+// frames copied but not read back yet
+const MAX_PENDING_FRAMES: usize = 3;
+// frames read back but not written yet
+const MAX_QUEUED_FRAMES: usize = 4;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordingMode {
+    // saves one frame out of every n, leaving the game running in real time
+    EveryNth(u32),
+    // saves every frame, and the game should step by this many seconds each frame however long it really took,
+    // so the sequence plays back smoothly at 1 / timestep frames per second
+    FixedTimestep(f32),
+}
+
+pub struct Recorder {
+    mode: RecordingMode,
+    directory: PathBuf,
+    // frames rendered since recording started
+    rendered: u64,
+    // frames saved, used to number the files
+    captured: u64,
+    // copied this frame, and not mapped until the frame is submitted
+    copying: Option<(u64, Readback)>,
+    // oldest first
+    pending: VecDeque<(u64, PendingReadback)>,
+    // buffers from frames that have been read, to copy later frames into
+    free_buffers: Vec<Buffer>,
+    sender: Option<mpsc::SyncSender<(PathBuf, RgbaImage)>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn new<P: AsRef<Path>>(directory: P, mode: RecordingMode) -> Result<Recorder> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory).with_context(|| format!("couldn't create {}", directory.display()))?;
+        let (sender, receiver) = mpsc::sync_channel::<(PathBuf, RgbaImage)>(MAX_QUEUED_FRAMES);
+        let writer = thread::spawn(move || {
+            for (path, image) in receiver {
+                if let Err(err) = image.save(&path) {
+                    log::warn!("couldn't save recorded frame {}: {}", path.display(), err);
+                }
+            }
+        });
+        Result::Ok(Recorder {
+            mode,
+            directory,
+            rendered: 0,
+            captured: 0,
+            copying: None,
+            pending: VecDeque::new(),
+            free_buffers: Vec::new(),
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn mode(&self) -> RecordingMode {
+        self.mode
+    }
+
+    // call with the finished frame before the encoder is submitted, copies it if this frame is one to keep
+    pub fn capture(&mut self, device: &Device, encoder: &mut CommandEncoder, frame: &wgpu::Texture) {
+        let keep = match self.mode {
+            RecordingMode::EveryNth(n) => self.rendered % n.max(1) as u64 == 0,
+            RecordingMode::FixedTimestep(_) => true,
+        };
+        self.rendered += 1;
+        if !keep {
+            return;
+        }
+        while self.pending.len() >= MAX_PENDING_FRAMES {
+            device.poll(wgpu::Maintain::Wait);
+            self.write_ready();
+        }
+        match Readback::reusing(device, encoder, frame, self.free_buffers.pop()) {
+            Result::Ok(readback) => {
+                self.copying = Some((self.captured, readback));
+                self.captured += 1;
+            }
+            Err(err) => log::warn!("couldn't record frame: {}", err),
+        }
+    }
+
+    // call after the encoder is submitted, starts mapping this frame's copy and passes on any earlier ones that are ready
+    pub fn collect(&mut self, device: &Device) {
+        if let Some((index, readback)) = self.copying.take() {
+            self.pending.push_back((index, readback.map()));
+        }
+        device.poll(wgpu::Maintain::Poll);
+        self.write_ready();
+    }
+
+    // passes every frame at the front of the queue that's been read back on to the writer, in order
+    fn write_ready(&mut self) {
+        while let Some((index, pending)) = self.pending.front_mut() {
+            let Some(result) = pending.try_read() else {
+                break;
+            };
+            let path = self.directory.join(format!("frame_{:06}.png", index));
+            match result {
+                Result::Ok(image) => {
+                    if let Some(sender) = &self.sender {
+                        let _ = sender.send((path, image));
+                    }
+                }
+                Err(err) => log::warn!("couldn't read back recorded frame {}: {}", path.display(), err),
+            }
+            if let Some((_, pending)) = self.pending.pop_front() {
+                self.free_buffers.push(pending.into_buffer());
+            }
+        }
+    }
+
+    // waits for every captured frame to be read back and written
+    pub fn finish(mut self, device: &Device) {
+        // a copy recorded without its frame being submitted will never finish
+        self.copying = None;
+        while !self.pending.is_empty() {
+            device.poll(wgpu::Maintain::Wait);
+            self.collect(device);
+        }
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        log::info!("recorded {} frames to {}", self.captured, self.directory.display());
+    }
+}
@@ -29,5 +29,9 @@ var g_buffer_sampler: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return textureSample(albedo, g_buffer_sampler, in.tex_coords) * vec4f(in.color, 0);
+    let albedo_color = textureSample(albedo, g_buffer_sampler, in.tex_coords);
+    let normal_color = textureSample(normal, g_buffer_sampler, in.tex_coords);
+    // unlit sprites write a zero normal, and partly transparent ones blend part of the way towards it
+    let unlit = 1.0 - saturate(length(normal_color.xyz * 2.0 - vec3f(1.0, 1.0, 1.0)) / 0.9);
+    return vec4f(albedo_color.xyz * mix(in.color, vec3f(1.0, 1.0, 1.0), unlit), 0.0);
 }
\ No newline at end of file
@@ -59,7 +59,10 @@ impl InputHandler {
                     winit::event::ElementState::Released => KeyState::Released,
                 };
                 for &index in indices {
-                    self.key_state[index] = state;
+                    // held keys repeat their pressed events, which shouldn't count as new presses
+                    if !(matches!(state, KeyState::Pressed) && self.key_state[index].is_pressed()) {
+                        self.key_state[index] = state;
+                    }
                 }
             }
         }
@@ -84,6 +87,12 @@ impl InputHandler {
             false
         }
     }
+    // only true on the first update after the control is pressed
+    pub fn just_pressed(&self, control: Control) -> bool {
+        self.control_map.get(&control).is_some_and(|indices| {
+            indices.iter().any(|&index| matches!(self.key_state[index], KeyState::Pressed))
+        })
+    }
     pub fn reset_states(&mut self) {
         for state in self.key_state.iter_mut() {
             *state = KeyState::default();
@@ -97,6 +106,13 @@ pub enum Control {
     MoveDown,
     MoveLeft,
     MoveRight,
+    ShowDebug,
+    ToggleCrt,
+    NextResolution,
+    NextScaleMode,
+    ToggleSharpBilinear,
+    Screenshot,
+    Record,
 }
 #[derive(Copy, Clone, Debug)]
 enum KeyState {
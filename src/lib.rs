@@ -1,14 +1,26 @@
-use assets::TextureAtlasStorage;
-use gfx::pass::lighting_pass::{Color, DynamicLight};
+use animation::{
+    controller::{update_controllers, AnimationController, AnimationGraph},
+    update_animations, AnimatedSprite,
+};
+use assets::{FontStorage, TextureAtlasStorage};
+use gfx::{recording::RecordingMode, post::{bloom::Bloom, color_grading::{ColorGrading, Lut}, vignette::Vignette}, pass::{deferred_pass::SpriteTransform, lighting_pass::{Color, DynamicLight}, overlay_pass::{BlendMode, OverlayOptions, Space}}};
 use hecs::World;
 use input::{Control, InputHandler};
-use renderer::RendererState;
+use parallax::{LayerLighting, ParallaxLayer, ParallaxLayers};
+use particles::{EmitterConfig, Gradient, ParticleEmitter, ParticleSprite};
+use renderer::{RendererState, ScreenshotSource};
+use settings::{Settings, SETTINGS_PATH};
 use row666_metroidbrainia_macros::Vertex;
 use std::{
     ops::{Add, AddAssign, Neg},
-    time::Instant,
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
+use text::{BitmapFont, TextStyle, TextTarget};
 use texture_atlas::{DeferredTextureRegion, TextureRegion};
+use tilemap::{autotile::AutotileRules, Room, TILE_SIZE};
 use winit::{
     dpi::PhysicalSize,
     event::{ElementState, Event, KeyEvent, WindowEvent},
@@ -17,16 +29,26 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod animation;
 mod assets;
 mod camera;
 mod input;
+mod nine_slice;
+mod parallax;
+mod particles;
 mod renderer;
+mod settings;
+mod text;
 mod texture;
 mod texture_atlas;
+mod tilemap;
 mod window;
 mod gfx;
 
 
+const SCREENSHOT_DIR: &str = "screenshots";
+const RECORDING_DIR: &str = "recordings";
+
 pub async fn run() {
     env_logger::init();
     let window = window::Window::new();
@@ -42,7 +64,8 @@ pub async fn run() {
             game.renderer.render().unwrap();
         },
         window::WindowEvent::LostFocus => game.input_handler.reset_states(),
-        _ => (),
+        // finishes writing any frames still being recorded
+        window::WindowEvent::Closed => game.renderer.stop_recording(),
     });
 }
 
@@ -50,8 +73,14 @@ pub async fn run() {
 struct Game {
     renderer: RendererState,
     texture_storage: TextureAtlasStorage,
+    fonts: FontStorage,
+    // for debug text
+    font: Rc<BitmapFont>,
     world: World,
     input_handler: InputHandler,
+    parallax: ParallaxLayers,
+    settings: Settings,
+    last_update: Instant,
 }
 struct Position(Vec2);
 struct Velocity(Vec2);
@@ -122,17 +151,63 @@ struct Light {
 impl Game {
     async fn new(window: &Window) -> Game {
         let mut texture_storage = TextureAtlasStorage::new();
-        let renderer = RendererState::new(window, &mut texture_storage).await;
+        let mut renderer = RendererState::new(window, &mut texture_storage).await;
+        let bloom = Bloom::new(renderer.texture_creator().device);
+        renderer.post_effects().push(Box::new(bloom));
+        let creator = renderer.texture_creator();
+        let grading = ColorGrading::new(creator.device, Lut::identity(creator.device, creator.queue, 16));
+        renderer.post_effects().push(Box::new(grading));
+        let vignette = Vignette::new(renderer.texture_creator().device);
+        renderer.post_effects().push(Box::new(vignette));
         let textures = texture_storage
             .load("textures", &renderer.texture_creator())
             .unwrap();
         let entities = textures.get_region("entities").unwrap().unwrap_atlas();
         let mut world = World::new();
+        let graph = Arc::new(AnimationGraph::load("assets/animations/player.json").unwrap());
+        let animation = entities.get("zombie_anim").expect("the atlas is missing the zombie_anim animation");
+        let (controller, sprite) = AnimationController::new(graph, animation).unwrap();
         world.spawn((
             Position((0.0, 0.0).into()),
-            entities.get("zombie").unwrap().unwrap_pair(),
+            controller,
+            sprite,
             PlayerControlled,
             Velocity((0., 0.).into()),
+            SpriteTransform::default(),
+        ));
+        world.spawn((
+            Position((0.0, 0.0).into()),
+            entities.get("target").unwrap().unwrap_single(),
+            OverlayOptions {
+                layer: 1,
+                ..Default::default()
+            },
+        ));
+        world.spawn((
+            Position((20.0, 0.0).into()),
+            entities.get("snowball").unwrap().unwrap_single(),
+        ));
+        world.spawn((
+            Position((-40.0, -40.0).into()),
+            ParticleEmitter::new(EmitterConfig {
+                spawn_rate: 20.0,
+                max_particles: 64,
+                lifetime: (0.4, 0.8),
+                direction: std::f32::consts::FRAC_PI_2,
+                spread: 0.6,
+                speed: (40.0, 70.0),
+                gravity: (0.0, -120.0),
+                color_over_life: Gradient::new(vec![
+                    (0.0, [1.0, 0.9, 0.5, 1.0]),
+                    (1.0, [1.0, 0.3, 0.0, 0.0]),
+                ]).unwrap(),
+                size_over_life: Gradient::new(vec![(0.0, 0.3), (1.0, 0.1)]).unwrap(),
+                sprite: Some(ParticleSprite::Unlit(
+                    entities.get("snowball").unwrap().unwrap_single(),
+                    BlendMode::Additive,
+                )),
+                light_radius: Some(24.0),
+            }, 0).unwrap(),
         ));
         world.spawn((
             Position((0.0, 0.0).into()),
@@ -183,23 +258,140 @@ impl Game {
             },
         ));
 
+        let room = Room::load("assets/rooms/room.rm").unwrap();
+        let walls = AutotileRules::load("assets/rooms/wall_autotile.json")
+            .unwrap()
+            .apply(&room);
+        let tiles = textures.get_region("tiles").unwrap().unwrap_atlas();
+        let wall_tiles = tiles["wall"].unwrap_atlas();
+        // the room is centred on the origin, and each wall is turned and flipped around the middle of its cell
+        let origin = (-(room.width() as f32) * TILE_SIZE / 2.0, room.height() as f32 * TILE_SIZE / 2.0);
+        for wall in walls {
+            let Some(region) = wall_tiles.get(&wall.variant) else {
+                log::warn!("no wall tile called {}", wall.variant);
+                continue;
+            };
+            let mut region = region.unwrap_single();
+            region.pivot = (TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+            let (x, y) = room.cell_position(wall.x, wall.y);
+            world.spawn((
+                Position((origin.0 + x + TILE_SIZE / 2.0, origin.1 + y + TILE_SIZE / 2.0).into()),
+                region,
+                wall.transform.sprite_transform(),
+            ));
+        }
+
+        let parallax = ParallaxLayers {
+            background: vec![ParallaxLayer {
+                repeat: (true, true),
+                tint: [0.3, 0.3, 0.4, 1.0],
+                ..ParallaxLayer::new(tiles["floor"].unwrap_single(), (0.25, 0.25), LayerLighting::Unlit)
+            }],
+            foreground: vec![ParallaxLayer {
+                offset: (0.0, -90.0),
+                repeat: (true, false),
+                tint: [0.8, 0.8, 0.9, 0.2],
+                ..ParallaxLayer::new(tiles["floor"].unwrap_single(), (1.5, 1.0), LayerLighting::Unlit)
+            }],
+        };
 
         let mut input_handler = InputHandler::new();
         input_handler.register_control(KeyCode::KeyW, Control::MoveUp);
         input_handler.register_control(KeyCode::KeyA, Control::MoveLeft);
         input_handler.register_control(KeyCode::KeyS, Control::MoveDown);
         input_handler.register_control(KeyCode::KeyD, Control::MoveRight);
+        input_handler.register_control(KeyCode::F3, Control::ShowDebug);
+        input_handler.register_control(KeyCode::F4, Control::ToggleCrt);
+        input_handler.register_control(KeyCode::F5, Control::NextResolution);
+        input_handler.register_control(KeyCode::F6, Control::NextScaleMode);
+        input_handler.register_control(KeyCode::F7, Control::ToggleSharpBilinear);
+        input_handler.register_control(KeyCode::F12, Control::Screenshot);
+        input_handler.register_control(KeyCode::F10, Control::Record);
+
+        let mut fonts = FontStorage::new();
+        let font = fonts.load("small", &renderer.texture_creator()).unwrap();
+
+        let settings = Settings::load(SETTINGS_PATH);
+        settings.apply(&mut renderer);
 
         Game {
             renderer,
             texture_storage,
+            fonts,
+            font,
             world,
             input_handler,
+            parallax,
+            settings,
+            last_update: Instant::now(),
+        }
+    }
+
+    fn apply_settings(&mut self) {
+        self.settings.apply(&mut self.renderer);
+        if let Err(err) = self.settings.save(SETTINGS_PATH) {
+            log::warn!("couldn't save settings: {}", err);
+        }
+    }
+
+    // saves the last frame at the internal resolution into the screenshots folder, named by when it was taken
+    fn save_screenshot(&self) {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(SCREENSHOT_DIR).join(format!("{}.png", since_epoch.as_millis()));
+        let result = std::fs::create_dir_all(SCREENSHOT_DIR)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.renderer.capture_screenshot(ScreenshotSource::Native, &path));
+        match result {
+            Ok(()) => log::info!("saved screenshot to {}", path.display()),
+            Err(err) => log::warn!("couldn't save screenshot: {:#}", err),
+        }
+    }
+
+    // records every frame at 60 frames per second of game time into a new folder under recordings
+    fn toggle_recording(&mut self) {
+        if self.renderer.is_recording() {
+            self.renderer.stop_recording();
+            return;
+        }
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let directory = Path::new(RECORDING_DIR).join(since_epoch.as_millis().to_string());
+        match self.renderer.start_recording(&directory, RecordingMode::FixedTimestep(1.0 / 60.0)) {
+            Ok(()) => log::info!("recording to {}", directory.display()),
+            Err(err) => log::warn!("couldn't start recording: {:#}", err),
         }
     }
 
     fn update(&mut self) {
-        for (_, (vel, _)) in self.world.query_mut::<(&mut Velocity, &PlayerControlled)>() {
+        let now = Instant::now();
+        // recordings step the same amount every frame, however long capturing takes
+        let dt = self.renderer.recording_timestep().unwrap_or((now - self.last_update).as_secs_f32());
+        self.last_update = now;
+        if let Some(grading) = self.renderer.post_effects().get_mut::<ColorGrading>("color_grading") {
+            grading.update(dt);
+        }
+        if self.input_handler.just_pressed(Control::ToggleCrt) {
+            self.settings.crt_filter = !self.settings.crt_filter;
+            self.apply_settings();
+        }
+        if self.input_handler.just_pressed(Control::NextResolution) {
+            self.settings.next_resolution();
+            self.apply_settings();
+        }
+        if self.input_handler.just_pressed(Control::NextScaleMode) {
+            self.settings.scale_mode = self.settings.scale_mode.next();
+            self.apply_settings();
+        }
+        if self.input_handler.just_pressed(Control::ToggleSharpBilinear) {
+            self.settings.sharp_bilinear = !self.settings.sharp_bilinear;
+            self.apply_settings();
+        }
+        if self.input_handler.just_pressed(Control::Screenshot) {
+            self.save_screenshot();
+        }
+        if self.input_handler.just_pressed(Control::Record) {
+            self.toggle_recording();
+        }
+        for (_, (vel, transform, _)) in self.world.query_mut::<(&mut Velocity, &mut SpriteTransform, &PlayerControlled)>() {
             vel.0 = (0., 0.).into();
             if self.input_handler.is_pressed(Control::MoveUp) {
                 vel.0 += (0., 1.)
@@ -213,6 +405,10 @@ impl Game {
             if self.input_handler.is_pressed(Control::MoveRight) {
                 vel.0 += (1., 0.)
             }
+            // face the way we're walking, flipping around the pivot
+            if vel.0.x != 0.0 {
+                transform.scale.0 = vel.0.x.signum();
+            }
         }
         for (_, (acc, pos, drift)) in self.world.query_mut::<(&mut Acceleration, &Position, &mut RandomDrift)>() {
             if pos.0.x * drift.current_dir.0 > 0.0 {
@@ -230,14 +426,69 @@ impl Game {
         for (_, (pos, vel)) in self.world.query_mut::<(&mut Position, &Velocity)>() {
             pos.0 += vel.0
         }
+        for (_, (pos, emitter)) in self.world.query_mut::<(&Position, &mut ParticleEmitter)>() {
+            emitter.update((pos.0.x, pos.0.y), dt)
+        }
+        for (_, (vel, controller)) in self.world.query_mut::<(&Velocity, &mut AnimationController)>() {
+            controller.set_float("speed", (vel.0.x * vel.0.x + vel.0.y * vel.0.y).sqrt());
+            controller.set_float("velocity_x", vel.0.x);
+            controller.set_float("velocity_y", vel.0.y);
+        }
+        update_controllers(&mut self.world);
+        for finished in update_animations(&mut self.world, dt) {
+            log::debug!("animation finished on {:?}", finished.0);
+        }
+
+        if let Err(err) = self.draw() {
+            log::warn!("couldn't draw the scene: {:#}", err);
+        }
+
+        if self.input_handler.is_pressed(Control::ShowDebug) {
+            let stats = self.renderer.stats();
+            log::debug!("{:?}", stats);
+            let text = format!(
+                "SPRITES {}/{}\nLIGHTS {}/{}",
+                stats.sprites.drawn, stats.sprites.submitted, stats.lights.drawn, stats.lights.submitted
+            );
+            let options = OverlayOptions {
+                space: Space::Screen,
+                layer: 100,
+                ..Default::default()
+            };
+            if let Err(err) = self.font.draw(&mut self.renderer, (2.0, 2.0), &text, &TextStyle::default(), TextTarget::Overlay(options)) {
+                log::warn!("couldn't draw debug text: {:#}", err);
+            }
+            for (_, (pos, light)) in self.world.query_mut::<(&Position, &Light)>() {
+                self.renderer.debug().circle((pos.0.x, pos.0.y), light.radius, [1.0, 1.0, 0.0, 0.5], Space::World);
+                self.renderer.debug().point((pos.0.x, pos.0.y), [1.0, 1.0, 1.0, 1.0], Space::World);
+            }
+            for (_, (pos, vel)) in self.world.query_mut::<(&Position, &Velocity)>() {
+                let to = pos.0 + Vec2::from((vel.0.x * 20.0, vel.0.y * 20.0));
+                self.renderer.debug().arrow((pos.0.x, pos.0.y), (to.x, to.y), 3.0, [0.0, 1.0, 1.0, 1.0], Space::World);
+            }
+        }
+
+        self.input_handler.update();
+    }
 
-        for (_, (pos, sprite)) in self
+    fn draw(&mut self) -> anyhow::Result<()> {
+        self.parallax.draw_background(&mut self.renderer)?;
+        for (_, (pos, sprite, transform)) in self
             .world
-            .query::<(&Position, &DeferredTextureRegion)>()
+            .query::<(&Position, &DeferredTextureRegion, Option<&SpriteTransform>)>()
             .iter()
         {
-            self.renderer
-                .draw_deferred_sprite((pos.0.x, pos.0.y, 0.), sprite.clone())
+            self.renderer.draw_deferred_sprite_transformed(
+                (pos.0.x, pos.0.y, 0.),
+                sprite.clone(),
+                transform.copied().unwrap_or_default(),
+            )?
+        }
+        for (_, (pos, sprite, transform)) in self.world.query_mut::<(&Position, &AnimatedSprite, Option<&SpriteTransform>)>() {
+            sprite.draw_transformed(&mut self.renderer, (pos.0.x, pos.0.y, 0.), transform.copied().unwrap_or_default())?
+        }
+        for (_, emitter) in self.world.query_mut::<&ParticleEmitter>() {
+            emitter.draw(&mut self.renderer)?
         }
         for (_, (pos, &light,)) in self.world.query_mut::<(&Position, &Light,)>() {
             self.renderer.draw_light(DynamicLight {
@@ -246,10 +497,28 @@ impl Game {
                 color: light.color,
             });
         }
-        //self.renderer.draw_sprite((0.0, 0.0, 0.0), self.textures.load("entities", &self.renderer.texture_creator()).unwrap().get_region("target").unwrap().unwrap_single());
-        //self.renderer.draw_sprite((20.0, 0.0, 0.0), self.textures.load("entities", &self.renderer.texture_creator()).unwrap().get_region("snowball").unwrap().unwrap_single());
-
-        self.input_handler.update();
+        for (_, (pos, sprite, transform)) in self
+            .world
+            .query::<(&Position, &TextureRegion, Option<&SpriteTransform>)>()
+            .without::<&OverlayOptions>()
+            .iter()
+        {
+            self.renderer.draw_sprite_transformed(
+                (pos.0.x, pos.0.y, 0.),
+                sprite.clone(),
+                transform.copied().unwrap_or_default(),
+            )?
+        }
+        self.parallax.draw_foreground(&mut self.renderer)?;
+        for (_, (pos, sprite, &options)) in self
+            .world
+            .query::<(&Position, &TextureRegion, &OverlayOptions)>()
+            .iter()
+        {
+            self.renderer
+                .draw_overlay_sprite((pos.0.x, pos.0.y), sprite.clone(), options)
+        }
+        Ok(())
     }
 }
 
@@ -0,0 +1,284 @@
+use anyhow::Result;
+
+use crate::{
+    gfx::pass::overlay_pass::{OverlayOptions, Space},
+    renderer::RendererState,
+    texture_atlas::{DeferredTextureRegion, Insets, Rect, Region, TextureRegion},
+};
+
+/*
+   Nine slice sprites keep their corners at their original size and stretch or tile the edges and centre to fill any size,
+   using the borders stored with the region in the atlas.
+
+   Like text, pieces are laid out with y going down from the top left, and converted to world or screen space when drawn.
+   If the size is smaller than the borders, the borders are shrunk to fit and the middle is left out.
+*/
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceFill {
+    Stretch,
+    // repeats the edges and centre at their original size, cutting the last copy short at the nearest whole pixel
+    Tile,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum SliceTarget {
+    // uses the normal map slices for normal pairs, and the default normal otherwise
+    Lit,
+    // only uses the albedo, for ui panels
+    Overlay(OverlayOptions),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SlicePiece {
+    // from the top left of the nine slice, with y going down
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+    // from the top left of the image
+    pub src: Rect,
+}
+
+#[derive(Clone, Debug)]
+enum SliceImage {
+    Single(TextureRegion),
+    Pair(DeferredTextureRegion),
+}
+
+#[derive(Clone, Debug)]
+pub struct NineSlice {
+    image: SliceImage,
+    insets: Insets,
+    pub fill: SliceFill,
+}
+
+#[allow(dead_code)]
+impl NineSlice {
+    // None if the region isn't a Single or NormalPair region with nine slice borders that fit inside it
+    pub fn new(region: &Region, fill: SliceFill) -> Option<NineSlice> {
+        match region {
+            Region::Single(image) => NineSlice::from_region(image.clone(), fill),
+            Region::NormalPair(image) => NineSlice::from_pair(image.clone(), fill),
+            _ => None,
+        }
+    }
+    pub fn from_region(image: TextureRegion, fill: SliceFill) -> Option<NineSlice> {
+        Some(NineSlice {
+            insets: image.nine_slice.filter(|insets| insets.fits(image.src))?,
+            image: SliceImage::Single(image),
+            fill,
+        })
+    }
+    pub fn from_pair(image: DeferredTextureRegion, fill: SliceFill) -> Option<NineSlice> {
+        Some(NineSlice {
+            insets: image.nine_slice.filter(|insets| insets.fits(image.albedo))?,
+            image: SliceImage::Pair(image),
+            fill,
+        })
+    }
+
+    fn src(&self) -> Rect {
+        match &self.image {
+            SliceImage::Single(image) => image.src,
+            SliceImage::Pair(image) => image.albedo,
+        }
+    }
+
+    pub fn pieces(&self, size: (f32, f32)) -> Vec<SlicePiece> {
+        let src = self.src();
+        let columns = spans(src.width, self.insets.left, self.insets.right, size.0, self.fill);
+        let rows = spans(src.height, self.insets.top, self.insets.bottom, size.1, self.fill);
+        let mut pieces = Vec::with_capacity(columns.len() * rows.len());
+        for row in rows.iter() {
+            for column in columns.iter() {
+                pieces.push(SlicePiece {
+                    position: (column.dest_start, row.dest_start),
+                    size: (column.dest_length, row.dest_length),
+                    src: Rect {
+                        x: column.src_start,
+                        y: row.src_start,
+                        width: column.src_length,
+                        height: row.src_length,
+                    },
+                });
+            }
+        }
+        pieces
+    }
+
+    // position is the bottom left in world space and the top left in screen space, like sized sprites
+    pub fn draw(
+        &self,
+        renderer: &mut RendererState,
+        position: (f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        target: SliceTarget,
+    ) -> Result<()> {
+        for piece in self.pieces(size) {
+            let offset = |rect: Rect| Rect {
+                x: rect.x + piece.src.x,
+                y: rect.y + piece.src.y,
+                width: piece.src.width,
+                height: piece.src.height,
+            };
+            let world_position = (
+                position.0 + piece.position.0,
+                position.1 + size.1 - piece.position.1 - piece.size.1,
+            );
+            match (&self.image, target) {
+                (SliceImage::Single(image), SliceTarget::Lit) => renderer.draw_tinted_sprite(
+                    (world_position.0, world_position.1, 0.0),
+                    piece.size,
+                    tint,
+                    TextureRegion {
+                        texture: image.texture.clone(),
+                        src: offset(image.src),
+                        pivot: (0.0, 0.0),
+                        nine_slice: None,
+                    },
+                )?,
+                (SliceImage::Pair(image), SliceTarget::Lit) => renderer.draw_tinted_deferred_sprite(
+                    (world_position.0, world_position.1, 0.0),
+                    piece.size,
+                    tint,
+                    DeferredTextureRegion {
+                        texture: image.texture.clone(),
+                        albedo: offset(image.albedo),
+                        normal: offset(image.normal),
+                        pivot: (0.0, 0.0),
+                        nine_slice: None,
+                    },
+                )?,
+                (image, SliceTarget::Overlay(options)) => {
+                    let texture = match image {
+                        SliceImage::Single(image) => image.texture.clone(),
+                        SliceImage::Pair(image) => image.texture.clone(),
+                    };
+                    let piece_position = match options.space {
+                        Space::World => world_position,
+                        Space::Screen => (position.0 + piece.position.0, position.1 + piece.position.1),
+                    };
+                    renderer.draw_overlay_sprite_sized(
+                        piece_position,
+                        piece.size,
+                        TextureRegion {
+                            texture,
+                            src: offset(self.src()),
+                            pivot: (0.0, 0.0),
+                            nine_slice: None,
+                        },
+                        OverlayOptions { tint, ..options },
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Span {
+    src_start: u32,
+    src_length: u32,
+    dest_start: f32,
+    dest_length: f32,
+}
+
+// splits one axis of the image into its start border, middle and end border, fitted to target
+fn spans(length: u32, start: u32, end: u32, target: f32, fill: SliceFill) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let borders = (start + end) as f32;
+    if target < borders {
+        let scale = target / borders;
+        spans.push(Span {
+            src_start: 0,
+            src_length: start,
+            dest_start: 0.0,
+            dest_length: start as f32 * scale,
+        });
+        spans.push(Span {
+            src_start: length - end,
+            src_length: end,
+            dest_start: start as f32 * scale,
+            dest_length: end as f32 * scale,
+        });
+    } else {
+        spans.push(Span {
+            src_start: 0,
+            src_length: start,
+            dest_start: 0.0,
+            dest_length: start as f32,
+        });
+        let middle = length - start - end;
+        let middle_target = target - borders;
+        match fill {
+            SliceFill::Stretch => spans.push(Span {
+                src_start: start,
+                src_length: middle,
+                dest_start: start as f32,
+                dest_length: middle_target,
+            }),
+            SliceFill::Tile if middle > 0 => {
+                let mut offset = 0.0;
+                while offset < middle_target {
+                    // the source can only be cut at whole pixels, so the destination is too to keep the tile unscaled
+                    let length = ((middle_target - offset).round() as u32).min(middle);
+                    spans.push(Span {
+                        src_start: start,
+                        src_length: length,
+                        dest_start: start as f32 + offset,
+                        dest_length: length as f32,
+                    });
+                    offset += middle as f32;
+                }
+            }
+            SliceFill::Tile => (),
+        }
+        spans.push(Span {
+            src_start: length - end,
+            src_length: end,
+            dest_start: target - end as f32,
+            dest_length: end as f32,
+        });
+    }
+    spans.retain(|span| span.src_length > 0 && span.dest_length > 0.0);
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (src_start, src_length, dest_start, dest_length) for each span
+    fn layout(length: u32, start: u32, end: u32, target: f32, fill: SliceFill) -> Vec<(u32, u32, f32, f32)> {
+        spans(length, start, end, target, fill)
+            .into_iter()
+            .map(|span| (span.src_start, span.src_length, span.dest_start, span.dest_length))
+            .collect()
+    }
+
+    #[test]
+    fn stretch_fills_the_middle() {
+        assert_eq!(layout(12, 4, 4, 20.0, SliceFill::Stretch), vec![(0, 4, 0.0, 4.0), (4, 4, 4.0, 12.0), (8, 4, 16.0, 4.0)]);
+    }
+
+    #[test]
+    fn tiles_are_cut_short_without_scaling() {
+        assert_eq!(
+            layout(12, 4, 4, 18.0, SliceFill::Tile),
+            vec![(0, 4, 0.0, 4.0), (4, 4, 4.0, 4.0), (4, 4, 8.0, 4.0), (4, 2, 12.0, 2.0), (8, 4, 14.0, 4.0)]
+        );
+        // 1.3 pixels left over rounds down to one whole pixel
+        assert_eq!(
+            layout(12, 4, 4, 13.3, SliceFill::Tile),
+            vec![(0, 4, 0.0, 4.0), (4, 4, 4.0, 4.0), (4, 1, 8.0, 1.0), (8, 4, 9.3, 4.0)]
+        );
+    }
+
+    #[test]
+    fn borders_shrink_when_the_target_is_smaller() {
+        assert_eq!(layout(12, 4, 4, 4.0, SliceFill::Stretch), vec![(0, 4, 0.0, 2.0), (8, 4, 2.0, 2.0)]);
+        assert_eq!(layout(12, 6, 2, 4.0, SliceFill::Tile), vec![(0, 6, 0.0, 3.0), (10, 2, 3.0, 1.0)]);
+    }
+}
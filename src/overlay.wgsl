@@ -0,0 +1,61 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    dimensions: vec2f,
+};
+@group(1) @binding(0) // 1.
+var<uniform> camera: CameraUniform;
+
+struct OverlayVertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+    @location(2) tint: vec4f,
+    @location(3) screen_space: f32,
+}
+struct OverlayVertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+    @location(1) tint: vec4f,
+}
+
+@vertex
+fn vs_main(
+    model: OverlayVertexInput,
+) -> OverlayVertexOutput {
+    var out: OverlayVertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.tint = model.tint;
+    if model.screen_space > 0.5 {
+        // screen space is in internal pixels, with the origin in the top left and y going down
+        out.clip_position = vec4f(
+            model.position.x / camera.dimensions.x * 2.0 - 1.0,
+            1.0 - model.position.y / camera.dimensions.y * 2.0,
+            0.0,
+            1.0
+        );
+    } else {
+        out.clip_position = camera.view_proj * vec4f(model.position.xy, 0.0, 1.0);
+    }
+    return out;
+}
+
+@group(0) @binding(0)
+var t_overlay: texture_2d<f32>;
+@group(0) @binding(1)
+var s_overlay: sampler;
+
+// the sheet is stored as Rgba8Unorm but authored in srgb, and the target is Rgba8UnormSrgb,
+// so unlit sprites have to be decoded to come out looking like the source image
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + vec3f(0.055)) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
+}
+
+@fragment
+fn fs_main(in: OverlayVertexOutput) -> @location(0) vec4f {
+    let texel = textureSample(t_overlay, s_overlay, in.tex_coords);
+    let color = vec4f(srgb_to_linear(texel.rgb), texel.a) * in.tint;
+    // every blend mode expects premultiplied alpha
+    return vec4f(color.rgb * color.a, color.a);
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+
+use crate::{
+    gfx::pass::overlay_pass::OverlayOptions,
+    renderer::RendererState,
+    texture_atlas::TextureRegion,
+};
+
+/*
+   Parallax layers are images that scroll at a different speed to the gameplay layer as the camera moves.
+
+   Backgrounds are drawn into the deferred pass before everything else, so the scene covers them whether they are lit or not.
+   Foregrounds are drawn after the scene. Lit ones go into the deferred pass too, but unlit ones go into the overlay pass,
+   because a see through unlit layer in the G-buffer would blend its zero normal into the lit sprites under it
+   and leave them partly unlit. Unlit backgrounds have nothing lit under them but earlier background layers,
+   so put lit backgrounds in front of unlit ones if they need to be see through.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerDepth {
+    // behind the gameplay layer
+    Background,
+    // in front of the gameplay layer
+    Foreground,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerLighting {
+    // uses the default normal, so lights and the global light affect it like any other flat sprite
+    Lit,
+    // always shown at full brightness, like a sky or fog that lights shouldn't reach
+    Unlit,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParallaxLayer {
+    pub image: TextureRegion,
+    // how far the layer moves for each pixel the camera moves, 1.0 moves with the gameplay layer and 0.0 stays fixed on screen.
+    // foregrounds usually use more than 1.0
+    pub factor: (f32, f32),
+    // bottom left of the image when the camera is at the origin
+    pub offset: (f32, f32),
+    // tiles the image to fill the screen along each axis
+    pub repeat: (bool, bool),
+    pub lighting: LayerLighting,
+    pub tint: [f32; 4],
+}
+
+#[allow(dead_code)]
+impl ParallaxLayer {
+    pub fn new(image: TextureRegion, factor: (f32, f32), lighting: LayerLighting) -> ParallaxLayer {
+        ParallaxLayer {
+            image,
+            factor,
+            offset: (0.0, 0.0),
+            repeat: (false, false),
+            lighting,
+            tint: [1.0; 4],
+        }
+    }
+
+    // world position of the bottom left of the image for a camera position
+    pub fn origin(&self, camera: (f32, f32)) -> (f32, f32) {
+        (
+            self.offset.0 + camera.0 * (1.0 - self.factor.0),
+            self.offset.1 + camera.1 * (1.0 - self.factor.1),
+        )
+    }
+
+    pub fn draw(&self, renderer: &mut RendererState, depth: LayerDepth) -> Result<()> {
+        let camera = (renderer.camera.pos.x, renderer.camera.pos.y);
+        let origin = self.origin(camera);
+        let size = (self.image.src.width as f32, self.image.src.height as f32);
+        if size.0 <= 0.0 || size.1 <= 0.0 {
+            return Ok(());
+        }
+        let viewport = renderer.camera.size();
+        let columns = visible_copies(origin.0, size.0, camera.0, viewport.0, self.repeat.0);
+        let rows = visible_copies(origin.1, size.1, camera.1, viewport.1, self.repeat.1);
+        for row in rows {
+            for column in columns.clone() {
+                let position = (origin.0 + column as f32 * size.0, origin.1 + row as f32 * size.1, 0.0);
+                match (self.lighting, depth) {
+                    (LayerLighting::Lit, _) => renderer.draw_tinted_sprite(position, size, self.tint, self.image.clone())?,
+                    (LayerLighting::Unlit, LayerDepth::Background) => {
+                        renderer.draw_unlit_sprite(position, size, self.tint, self.image.clone())?
+                    }
+                    (LayerLighting::Unlit, LayerDepth::Foreground) => renderer.draw_overlay_sprite_sized(
+                        (position.0, position.1),
+                        size,
+                        self.image.clone(),
+                        OverlayOptions {
+                            tint: self.tint,
+                            ..Default::default()
+                        },
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// which copies of the image along one axis overlap the screen, or just the original if it doesn't repeat
+fn visible_copies(origin: f32, size: f32, camera: f32, viewport: f32, repeat: bool) -> std::ops::RangeInclusive<i32> {
+    if !repeat {
+        return 0..=0;
+    }
+    let first = ((camera - viewport / 2.0 - origin) / size).floor() as i32;
+    let last = ((camera + viewport / 2.0 - origin) / size).floor() as i32;
+    first..=last
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ParallaxLayers {
+    // both are drawn in order, so put the furthest layers first
+    pub background: Vec<ParallaxLayer>,
+    pub foreground: Vec<ParallaxLayer>,
+}
+
+impl ParallaxLayers {
+    // call before drawing the gameplay layer
+    pub fn draw_background(&self, renderer: &mut RendererState) -> Result<()> {
+        for layer in self.background.iter() {
+            layer.draw(renderer, LayerDepth::Background)?;
+        }
+        Ok(())
+    }
+    // call after drawing the gameplay layer
+    pub fn draw_foreground(&self, renderer: &mut RendererState) -> Result<()> {
+        for layer in self.foreground.iter() {
+            layer.draw(renderer, LayerDepth::Foreground)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{ensure, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    gfx::pass::{
+        lighting_pass::{Color, DynamicLight},
+        overlay_pass::{BlendMode, OverlayOptions},
+    },
+    renderer::RendererState,
+    texture_atlas::TextureRegion,
+};
+
+/*
+   Particles are simulated per emitter, and live in world space once spawned, so moving an emitter doesn't drag its particles along.
+
+   Every random value an emitter uses comes from its own seeded rng, so feeding an emitter the same sequence of timesteps
+   always produces the same particles.
+*/
+
+// longer frames only spawn this many seconds worth of particles, so a hitch doesn't flood the batch
+const MAX_SPAWN_STEP: f32 = 0.25;
+// the most particles one emitter can have alive. every sprite in a frame shares 16 bit indices,
+// so this leaves room for a few emitters alongside the rest of the scene
+const MAX_PARTICLES: usize = 4096;
+
+pub trait Lerp: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+impl Lerp for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let mut out = self;
+        for (out, other) in out.iter_mut().zip(other) {
+            *out = out.lerp(other, t);
+        }
+        out
+    }
+}
+
+// keys are (time, value) pairs, with time going from 0 at birth to 1 at death
+#[derive(Clone, Debug)]
+pub struct Gradient<T: Lerp> {
+    keys: Vec<(f32, T)>,
+}
+#[allow(dead_code)]
+impl<T: Lerp> Gradient<T> {
+    // fails if there are no keys, or any of their times aren't finite
+    pub fn new(mut keys: Vec<(f32, T)>) -> Result<Gradient<T>> {
+        ensure!(!keys.is_empty(), "gradients need at least one key");
+        ensure!(keys.iter().all(|(time, _)| time.is_finite()), "gradient key times have to be finite");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Gradient { keys })
+    }
+    pub fn constant(value: T) -> Gradient<T> {
+        Gradient {
+            keys: vec![(0.0, value)],
+        }
+    }
+    pub fn sample(&self, t: f32) -> T {
+        let first = self.keys[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.keys.windows(2) {
+            let ((start, from), (end, to)) = (pair[0], pair[1]);
+            if t <= end {
+                return from.lerp(to, (t - start) / (end - start));
+            }
+        }
+        self.keys[self.keys.len() - 1].1
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum ParticleSprite {
+    // drawn in the deferred pass, using the default normal
+    Lit(TextureRegion),
+    // drawn in the overlay pass
+    Unlit(TextureRegion, BlendMode),
+}
+
+#[derive(Clone, Debug)]
+pub struct EmitterConfig {
+    // particles per second
+    pub spawn_rate: f32,
+    // spawning pauses while this many particles are alive, up to MAX_PARTICLES
+    pub max_particles: usize,
+    // in seconds, picked uniformly between the two
+    pub lifetime: (f32, f32),
+    // the direction of the velocity cone in radians, with 0 pointing along +x
+    pub direction: f32,
+    // half the angle of the velocity cone in radians
+    pub spread: f32,
+    // in pixels per second
+    pub speed: (f32, f32),
+    // in pixels per second squared
+    pub gravity: (f32, f32),
+    // rgba, multiplied with the sprite and used as the light's color
+    pub color_over_life: Gradient<[f32; 4]>,
+    // multiplied with the sprite's size and the light's radius
+    pub size_over_life: Gradient<f32>,
+    pub sprite: Option<ParticleSprite>,
+    pub light_radius: Option<f32>,
+}
+
+impl EmitterConfig {
+    // ranges have to go from low to high, and nothing can be negative or infinite
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.spawn_rate.is_finite() && self.spawn_rate >= 0.0, "spawn rate {} isn't a positive number", self.spawn_rate);
+        ensure!(
+            0 < self.max_particles && self.max_particles <= MAX_PARTICLES,
+            "max particles {} has to be between 1 and {}",
+            self.max_particles,
+            MAX_PARTICLES
+        );
+        let (min_lifetime, max_lifetime) = self.lifetime;
+        ensure!(
+            min_lifetime.is_finite() && max_lifetime.is_finite() && 0.0 < min_lifetime && min_lifetime <= max_lifetime,
+            "lifetime range {:?} has to be positive and go from low to high",
+            self.lifetime
+        );
+        let (min_speed, max_speed) = self.speed;
+        ensure!(
+            min_speed.is_finite() && max_speed.is_finite() && min_speed <= max_speed,
+            "speed range {:?} has to go from low to high",
+            self.speed
+        );
+        ensure!(self.direction.is_finite() && self.spread.is_finite(), "direction and spread have to be finite");
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    position: (f32, f32),
+    velocity: (f32, f32),
+    age: f32,
+    lifetime: f32,
+}
+
+pub struct ParticleEmitter {
+    config: EmitterConfig,
+    pub active: bool,
+    rng: StdRng,
+    particles: Vec<Particle>,
+    spawn_accumulator: f32,
+}
+
+#[allow(dead_code)]
+impl ParticleEmitter {
+    // fails if the config isn't valid
+    pub fn new(config: EmitterConfig, seed: u64) -> Result<ParticleEmitter> {
+        config.validate()?;
+        Ok(ParticleEmitter {
+            config,
+            active: true,
+            rng: StdRng::seed_from_u64(seed),
+            particles: Vec::new(),
+            spawn_accumulator: 0.0,
+        })
+    }
+
+    pub fn config(&self) -> &EmitterConfig {
+        &self.config
+    }
+    // keeps the old config if the new one isn't valid
+    pub fn set_config(&mut self, config: EmitterConfig) -> Result<()> {
+        config.validate()?;
+        self.config = config;
+        Ok(())
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    // origin is where new particles spawn, dt is in seconds
+    pub fn update(&mut self, origin: (f32, f32), dt: f32) {
+        let gravity = self.config.gravity;
+        for particle in self.particles.iter_mut() {
+            particle.age += dt;
+            particle.velocity.0 += gravity.0 * dt;
+            particle.velocity.1 += gravity.1 * dt;
+            particle.position.0 += particle.velocity.0 * dt;
+            particle.position.1 += particle.velocity.1 * dt;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+
+        if !self.active {
+            return;
+        }
+        self.spawn_accumulator += self.config.spawn_rate * dt.min(MAX_SPAWN_STEP);
+        while self.spawn_accumulator >= 1.0 {
+            self.spawn_accumulator -= 1.0;
+            if self.particles.len() >= self.config.max_particles {
+                continue;
+            }
+            let particle = self.spawn(origin);
+            self.particles.push(particle);
+        }
+    }
+
+    fn spawn(&mut self, origin: (f32, f32)) -> Particle {
+        let config = &self.config;
+        let angle = config.direction + self.rng.gen_range(-1.0..=1.0) * config.spread;
+        let speed = self.rng.gen_range(config.speed.0..=config.speed.1);
+        let lifetime = self.rng.gen_range(config.lifetime.0..=config.lifetime.1);
+        Particle {
+            position: origin,
+            velocity: (angle.cos() * speed, angle.sin() * speed),
+            age: 0.0,
+            lifetime,
+        }
+    }
+
+    pub fn draw(&self, renderer: &mut RendererState) -> Result<()> {
+        for particle in self.particles.iter() {
+            let life = particle.age / particle.lifetime;
+            let color = self.config.color_over_life.sample(life);
+            let size = self.config.size_over_life.sample(life);
+            let (x, y) = particle.position;
+            match &self.config.sprite {
+                Some(ParticleSprite::Lit(image)) => {
+                    let (width, height) = (image.src.width as f32 * size, image.src.height as f32 * size);
+                    renderer.draw_tinted_sprite(
+                        (x - width / 2.0, y - height / 2.0, 0.0),
+                        (width, height),
+                        color,
+                        image.clone(),
+                    )?;
+                }
+                Some(ParticleSprite::Unlit(image, blend)) => {
+                    let (width, height) = (image.src.width as f32 * size, image.src.height as f32 * size);
+                    renderer.draw_overlay_sprite_sized(
+                        (x - width / 2.0, y - height / 2.0),
+                        (width, height),
+                        image.clone(),
+                        OverlayOptions {
+                            blend: *blend,
+                            tint: color,
+                            ..Default::default()
+                        },
+                    );
+                }
+                None => (),
+            }
+            if let Some(radius) = self.config.light_radius {
+                // fading out the alpha dims the light too
+                renderer.draw_light(DynamicLight {
+                    center: (x, y, 10.0),
+                    radius: radius * size,
+                    color: Color::from_rgb_f32(color[0] * color[3], color[1] * color[3], color[2] * color[3]),
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 struct Viewport {
     viewport: mat4x4<f32>,
+    texel_offset: vec4f,
+    filtering: vec4f,
+    // scanlines, mask, curvature, fringing
+    crt: vec4f,
 };
 @group(1) @binding(0) // 1.
 var<uniform> viewport: Viewport;
@@ -18,7 +22,7 @@ fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
+    out.tex_coords = model.tex_coords + viewport.texel_offset.xy;
     out.clip_position = viewport.viewport * vec4f(model.position[0], model.position[1], 0.0, 1.0);
     return out;
 }
@@ -28,7 +32,54 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// sharp bilinear keeps each texel flat and only blends across the last window pixel before the next one,
+// which needs a linear sampler and the number of window pixels each texel covers
+fn sharp_bilinear(tex_coords: vec2f) -> vec2f {
+    let size = vec2f(textureDimensions(t_diffuse));
+    let texel = tex_coords * size;
+    let scale = max(viewport.filtering.xy, vec2f(1.0, 1.0));
+    let region = 0.5 - 0.5 / scale;
+    let from_center = fract(texel) - 0.5;
+    let blend = (from_center - clamp(from_center, -region, region)) * scale + 0.5;
+    return (floor(texel) + blend) / size;
+}
+
+// bulges the image out from the centre like the glass of a crt, pushing the corners off the edges of the image
+fn curve(tex_coords: vec2f) -> vec2f {
+    let centered = tex_coords * 2.0 - 1.0;
+    let bent = centered * (1.0 + viewport.crt.z * centered.yx * centered.yx);
+    return bent * 0.5 + 0.5;
+}
+
+fn sample(tex_coords: vec2f) -> vec4f {
+    let coords = select(tex_coords, sharp_bilinear(tex_coords), viewport.filtering.z > 0.5);
+    return textureSample(t_diffuse, s_diffuse, coords);
+}
+
+// window_position is in window pixels, so the mask lines up with the real screen's pixels
+fn crt(tex_coords: vec2f, window_position: vec2f) -> vec4f {
+    let coords = curve(tex_coords);
+    let size = vec2f(textureDimensions(t_diffuse));
+    // red and blue are pulled apart more the further they are from the centre, like a misconverged beam
+    let fringe = (coords - 0.5) * 2.0 * viewport.crt.w / size;
+    let center = sample(coords);
+    var color = vec3f(sample(coords + fringe).r, center.g, sample(coords - fringe).b);
+    // brightest in the middle of each internal row, so the lines match the internal resolution at any scale
+    let row = fract(coords.y * size.y);
+    color *= mix(1.0, sin(row * 3.14159265), viewport.crt.x);
+    // each column of window pixels lets through one of red, green or blue at full strength
+    let stripe = u32(window_position.x) % 3u;
+    var mask = vec3f(1.0 - viewport.crt.y);
+    mask[stripe] = 1.0;
+    color *= mask;
+    let inside = all(coords >= vec2f(0.0, 0.0)) && all(coords <= vec2f(1.0, 1.0));
+    return select(vec4f(0.0, 0.0, 0.0, 1.0), vec4f(color, center.a), inside);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+    if viewport.filtering.w > 0.5 {
+        return crt(in.tex_coords, in.clip_position.xy);
+    }
+    return sample(in.tex_coords);
+}
@@ -1,33 +1,100 @@
-use std::{collections::HashMap, iter};
+use std::{collections::HashMap, iter, path::Path, sync::Arc};
+use anyhow::Context;
 use wgpu::util::DeviceExt;
 use winit::{dpi::PhysicalSize, window::Window};
 
+#[cfg(feature = "debug-draw")]
+use crate::gfx::pass::debug_pass::{DebugNode, DebugPass};
 use crate::{
-    assets::TextureAtlasStorage, camera::{Camera, CameraUniform, VIEWPORT_HEIGHT, VIEWPORT_WIDTH}, gfx::{context::GraphicsContext, pass::{deferred_pass::DeferredPass, lighting_pass::{self, DynamicLight, LightingPass}, present_pass::PresentPass}}, texture::TextureCreator, texture_atlas::{DeferredTextureRegion, TextureRegion}
+    assets::TextureAtlasStorage, camera::{Camera, CameraUniform, CullStats, DEFAULT_RESOLUTION}, gfx::{capture::Readback, context::{GraphicsContext, OffscreenTarget, PresentTarget, SurfaceTarget}, graph::{FrameData, RenderGraph, RenderNode, RenderTargets, TargetDesc, ALBEDO, LIT, NORMAL, SCENE}, recording::{Recorder, RecordingMode}, pass::{debug_pass::DebugDraw, deferred_pass::{DeferredPass, SpriteTransform}, lighting_pass::{self, DynamicLight, LightingPass}, overlay_pass::{OverlayOptions, OverlayPass}, present_pass::{CrtFilter, PresentPass, ScaleMode, Viewport}}, post::{PostStack, POST_FORMAT}}, texture::{Texture, TextureCreator}, texture_atlas::{DeferredTextureRegion, TextureRegion}
 };
 
-pub struct RendererState {
-    context: GraphicsContext,
+// what the last frame drew, after culling everything outside of view
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub sprites: CullStats,
+    pub lights: CullStats,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenshotSource {
+    // the lit scene at the internal resolution, before post effects and overlays, one pixel per internal pixel
+    Native,
+    // the whole frame as it was shown in the window, after upscaling and the crt filter
+    Presented,
+}
+
+// the built in passes' targets, which all match the internal resolution
+fn declare_internal_targets(targets: &mut RenderTargets, resolution: (u32, u32)) {
+    for (name, format) in [
+        (ALBEDO, wgpu::TextureFormat::Rgba8Unorm),
+        (NORMAL, wgpu::TextureFormat::Rgba8Unorm),
+        (LIT, POST_FORMAT),
+        (SCENE, POST_FORMAT),
+    ] {
+        targets.declare(name, TargetDesc { size: resolution, format });
+    }
+}
+
+// draws into a window's surface in the game, or into an offscreen texture when there's no window
+pub struct RendererState<T: PresentTarget = SurfaceTarget> {
+    context: GraphicsContext<T>,
     pub camera: Camera,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
-    viewport_matrix: [[f32; 4]; 4],
+    scale_mode: ScaleMode,
+    viewport: Viewport,
 
     
     deferred_pass: DeferredPass,
     lighting_pass: LightingPass,
+    post: PostStack,
+    overlay_pass: OverlayPass,
+    #[cfg(feature = "debug-draw")]
+    debug_pass: DebugPass,
     present_pass: PresentPass,
+    // passes added by the game, run by the graph along with the built in ones
+    nodes: Vec<Box<dyn RenderNode>>,
+    targets: RenderTargets,
+    debug: DebugDraw,
+    stats: RenderStats,
+    // the sub pixel offset the last frame was presented with, so screenshots of it match
+    subpixel_offset: (f32, f32),
+    recorder: Option<Recorder>,
 }
 
-impl RendererState {
-    pub async fn new(window: &Window, textures: &mut TextureAtlasStorage) -> RendererState {
-        let size = window.inner_size();
-
+impl RendererState<SurfaceTarget> {
+    pub async fn new(window: &Window, textures: &mut TextureAtlasStorage) -> RendererState<SurfaceTarget> {
         let context = GraphicsContext::new(window).await;
+        RendererState::with_context(context, textures)
+    }
+}
+
+#[allow(dead_code)]
+impl RendererState<OffscreenTarget> {
+    // renders frames of the given size without a window, on a software adapter if there's no GPU.
+    // fails if no adapter could be found at all
+    pub async fn headless(size: (u32, u32), textures: &mut TextureAtlasStorage) -> anyhow::Result<RendererState<OffscreenTarget>> {
+        let context = GraphicsContext::headless(size).await?;
+        Ok(RendererState::with_context(context, textures))
+    }
+
+    // the last presented frame, with the present pass's upscaling and filters
+    pub fn output(&self) -> &wgpu::Texture {
+        &self.context.target.texture.texture
+    }
+}
+
+impl<T: PresentTarget> RendererState<T> {
+    fn with_context(context: GraphicsContext<T>, textures: &mut TextureAtlasStorage) -> RendererState<T> {
+        let size = context.target.size();
 
         let camera = Camera {
             pos: (0.0, 0.0, 10.0).into(),
+            pixel_perfect: true,
+            resolution: DEFAULT_RESOLUTION,
         };
 
         let mut camera_uniform = CameraUniform::new();
@@ -62,34 +129,50 @@ impl RendererState {
             }],
             label: Some("camera_bind_group"),
         });
-        let max_scale =
-            (size.width as f32 / VIEWPORT_WIDTH).min(size.height as f32 / VIEWPORT_HEIGHT);
-        let viewport_matrix = cgmath::Matrix4::from_nonuniform_scale(
-            max_scale / (size.width as f32 / VIEWPORT_WIDTH),
-            max_scale / (size.height as f32 / VIEWPORT_HEIGHT),
-            1.0,
-        )
-        .into();
+        let scale_mode = ScaleMode::default();
+        let viewport = Viewport::new(scale_mode, size, camera.size());
         let sheet = textures.load("textures", &TextureCreator {
             device: &context.device,
             queue: &context.queue
         }).unwrap().image.clone();
         let deferred_pass = DeferredPass::new(&context.device, sheet);
         let lighting_pass = LightingPass::new(&context.device);
-        let present_pass = PresentPass::new(&context.device, context.config.format);
+        let post = PostStack::new(&context.device, camera.resolution);
+        let overlay_pass = OverlayPass::new(&context.device, POST_FORMAT);
+        #[cfg(feature = "debug-draw")]
+        let debug_pass = DebugPass::new(&context.device, POST_FORMAT);
+        let mut targets = RenderTargets::new();
+        declare_internal_targets(&mut targets, camera.resolution);
+        let present_pass = PresentPass::new(&context.device, context.target.format());
         Self {
             context,
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
-            viewport_matrix,
+            scale_mode,
+            viewport,
             deferred_pass,
             lighting_pass,
-            present_pass
+            post,
+            overlay_pass,
+            #[cfg(feature = "debug-draw")]
+            debug_pass,
+            present_pass,
+            nodes: Vec::new(),
+            targets,
+            debug: DebugDraw::default(),
+            stats: RenderStats::default(),
+            subpixel_offset: (0.0, 0.0),
+            recorder: None,
         }
     }
 
+    // whether sprites using texture can be drawn lit
+    pub fn is_on_sheet(&self, texture: &Arc<Texture>) -> bool {
+        self.deferred_pass.is_sheet(texture)
+    }
+
     pub fn texture_creator(&self) -> TextureCreator {
         TextureCreator {
             device: &self.context.device,
@@ -100,17 +183,41 @@ impl RendererState {
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
             self.context.resize(width, height);
-            let max_scale = (width as f32 / VIEWPORT_WIDTH)
-                .min(height as f32 / VIEWPORT_HEIGHT);
-            self.viewport_matrix = cgmath::Matrix4::from_nonuniform_scale(
-                max_scale / (width as f32 / VIEWPORT_WIDTH),
-                max_scale / (height as f32 / VIEWPORT_HEIGHT),
-                1.0,
-            )
-            .into();
+            self.update_viewport();
         }
     }
 
+    fn update_viewport(&mut self) {
+        self.viewport = Viewport::new(self.scale_mode, self.context.target.size(), self.camera.size());
+    }
+
+    #[allow(dead_code)]
+    pub fn internal_resolution(&self) -> (u32, u32) {
+        self.camera.resolution
+    }
+    // recreates every target at the internal resolution, so it's best not to call it every frame
+    pub fn set_internal_resolution(&mut self, resolution: (u32, u32)) {
+        if resolution == self.camera.resolution || resolution.0 == 0 || resolution.1 == 0 {
+            return;
+        }
+        self.camera.resolution = resolution;
+        declare_internal_targets(&mut self.targets, resolution);
+        self.post.resize(&self.context.device, resolution);
+        self.update_viewport();
+    }
+
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+        self.update_viewport();
+    }
+    // None turns the filter off
+    pub fn set_crt_filter(&mut self, crt: Option<CrtFilter>) {
+        self.present_pass.crt = crt;
+    }
+    pub fn set_sharp_bilinear(&mut self, enabled: bool) {
+        self.present_pass.sharp_bilinear = enabled;
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let mut encoder = self.context
             .device
@@ -118,20 +225,230 @@ impl RendererState {
                 label: Some("Render Encoder"),
             });
         
-        self.deferred_pass.draw(&self.context.device, &mut encoder, &self.camera_bind_group);
-        self.lighting_pass.draw(&self.context.device, &mut encoder, &self.camera_bind_group, &self.deferred_pass.albedo_buffer, &self.deferred_pass.normal_buffer);
-        self.present_pass.present(&self.context.device, encoder, &self.context.queue, &self.lighting_pass.output, self.viewport_matrix, &self.context.surface);
+        self.camera_uniform.update_view_proj(&self.camera);
+        self.context.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        let frame = FrameData {
+            camera_bind_group: &self.camera_bind_group,
+            view: self.camera.view_bounds(),
+        };
+        #[cfg(feature = "debug-draw")]
+        let mut debug_node = DebugNode {
+            pass: &self.debug_pass,
+            shapes: &mut self.debug,
+        };
+        let mut graph = RenderGraph::new();
+        graph.add(&mut self.deferred_pass);
+        graph.add(&mut self.lighting_pass);
+        // effects only see the lit scene, overlays and debug shapes are drawn on top of the result
+        graph.add(&mut self.post);
+        for node in self.nodes.iter_mut() {
+            graph.add(node.as_mut());
+        }
+        graph.add(&mut self.overlay_pass);
+        #[cfg(feature = "debug-draw")]
+        graph.add(&mut debug_node);
+        // a graph that can't run, like one with a cycle, fails before any node runs, so nothing has been drawn yet.
+        // the frame is dropped along with everything queued for it, so the queues don't keep growing while it's broken
+        if let Err(err) = graph.execute(&self.context.device, &self.context.queue, &mut encoder, &mut self.targets, &frame) {
+            log::error!("skipping a frame, couldn't run the render graph: {:#}", err);
+            self.deferred_pass.discard();
+            self.lighting_pass.discard();
+            self.overlay_pass.discard();
+            #[cfg(feature = "debug-draw")]
+            self.debug.clear();
+            return Ok(());
+        }
+        self.stats.sprites = self.deferred_pass.stats;
+        self.stats.lights = self.lighting_pass.stats;
+        let output = self.targets.texture(SCENE);
+        let (_, subpixel_offset) = self.camera.snapped();
+        self.subpixel_offset = subpixel_offset;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.capture(&self.context.device, &mut encoder, &output.texture);
+        }
+        let (device, present_pass, viewport) = (&self.context.device, &self.present_pass, &self.viewport);
+        self.context.target.present(&self.context.queue, encoder, |encoder, view| {
+            present_pass.draw(device, encoder, output, viewport, subpixel_offset, view)
+        })?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.collect(&self.context.device);
+        }
 
         Ok(())
     }
+    // saves the last rendered frame as a png. blocks until the GPU has copied it back
+    pub fn capture_screenshot<P: AsRef<Path>>(&self, source: ScreenshotSource, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        self.read_frame(source)?
+            .save(path)
+            .with_context(|| format!("couldn't save screenshot to {}", path.display()))
+    }
+    // copies the last rendered frame back from the GPU, blocking until it's done
+    pub fn read_frame(&self, source: ScreenshotSource) -> anyhow::Result<image::RgbaImage> {
+        let device = &self.context.device;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Screenshot Encoder"),
+        });
+        // a surface's own textures can't be read after they're presented, so the present pass is drawn again into a copy
+        let frame = |name| self.targets.get(name).context("nothing has been rendered yet");
+        let presented;
+        let texture = match source {
+            ScreenshotSource::Native => &frame(LIT)?.texture,
+            ScreenshotSource::Presented => {
+                let (width, height) = self.context.target.size();
+                presented = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("screenshot"),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: self.context.target.format(),
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+                let view = presented.create_view(&wgpu::TextureViewDescriptor::default());
+                self.present_pass.draw(device, &mut encoder, frame(SCENE)?, &self.viewport, self.subpixel_offset, &view);
+                &presented
+            }
+        };
+        let readback = Readback::new(device, &mut encoder, texture)?;
+        self.context.queue.submit(iter::once(encoder.finish()));
+        readback.read(device)
+    }
+    // saves rendered frames at the internal resolution, after post effects and overlays, into directory until stopped.
+    // replaces any recording already running
+    pub fn start_recording<P: AsRef<Path>>(&mut self, directory: P, mode: RecordingMode) -> anyhow::Result<()> {
+        self.stop_recording();
+        self.recorder = Some(Recorder::new(directory, mode)?);
+        Ok(())
+    }
+    // blocks until every frame recorded so far has been written
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(&self.context.device);
+        }
+    }
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+    // how far the game should step each frame while recording at a fixed timestep
+    pub fn recording_timestep(&self) -> Option<f32> {
+        match self.recorder.as_ref()?.mode() {
+            RecordingMode::FixedTimestep(timestep) => Some(timestep),
+            RecordingMode::EveryNth(_) => None,
+        }
+    }
+    // the node is run every frame, after post effects and before overlays unless what it reads and writes says otherwise.
+    // any textures it uses that the built in passes don't have to be declared first
+    #[allow(dead_code)]
+    pub fn add_render_node(&mut self, node: Box<dyn RenderNode>) {
+        self.nodes.push(node);
+    }
+    #[allow(dead_code)]
+    pub fn declare_target(&mut self, name: &'static str, desc: TargetDesc) {
+        self.targets.declare(name, desc);
+    }
+    // effects run in order on the lit scene every frame
+    pub fn post_effects(&mut self) -> &mut PostStack {
+        &mut self.post
+    }
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+    // shapes are drawn at the end of the next render, then cleared
+    pub fn debug(&mut self) -> &mut DebugDraw {
+        &mut self.debug
+    }
     pub fn draw_light(&mut self, light: DynamicLight) {
         self.lighting_pass.draw_light(light)
     }
+    #[allow(dead_code)]
     pub fn draw_deferred_sprite(
         &mut self,
         position: (f32, f32, f32),
         image: DeferredTextureRegion,
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_sprite(position, image, SpriteTransform::default())
+    }
+    pub fn draw_deferred_sprite_transformed(
+        &mut self,
+        position: (f32, f32, f32),
+        image: DeferredTextureRegion,
+        transform: SpriteTransform,
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_sprite(position, image, transform)
+    }
+    #[allow(dead_code)]
+    pub fn draw_sprite(&mut self, position: (f32, f32, f32), image: TextureRegion) -> anyhow::Result<()> {
+        self.deferred_pass.draw_single_sprite(position, image, None, SpriteTransform::default())
+    }
+    pub fn draw_sprite_transformed(
+        &mut self,
+        position: (f32, f32, f32),
+        image: TextureRegion,
+        transform: SpriteTransform,
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_single_sprite(position, image, None, transform)
+    }
+    #[allow(dead_code)]
+    pub fn draw_sprite_with_normal(
+        &mut self,
+        position: (f32, f32, f32),
+        image: TextureRegion,
+        normal: [f32; 3],
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_single_sprite(position, image, Some(normal), SpriteTransform::default())
+    }
+    pub fn draw_overlay_sprite(
+        &mut self,
+        position: (f32, f32),
+        image: TextureRegion,
+        options: OverlayOptions,
     ) {
-        self.deferred_pass.draw_sprite(position, image)
+        self.overlay_pass.draw_sprite(position, image, options)
+    }
+    pub fn draw_overlay_sprite_sized(
+        &mut self,
+        position: (f32, f32),
+        size: (f32, f32),
+        image: TextureRegion,
+        options: OverlayOptions,
+    ) {
+        self.overlay_pass.draw_sprite_sized(position, size, image, options)
+    }
+    pub fn draw_tinted_sprite(
+        &mut self,
+        position: (f32, f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        image: TextureRegion,
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_tinted_sprite(position, size, tint, image)
+    }
+    pub fn draw_tinted_deferred_sprite(
+        &mut self,
+        position: (f32, f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        image: DeferredTextureRegion,
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_tinted_pair(position, size, tint, image)
+    }
+    pub fn draw_unlit_sprite(
+        &mut self,
+        position: (f32, f32, f32),
+        size: (f32, f32),
+        tint: [f32; 4],
+        image: TextureRegion,
+    ) -> anyhow::Result<()> {
+        self.deferred_pass.draw_unlit_sprite(position, size, tint, image)
+    }
+    #[allow(dead_code)]
+    pub fn set_default_normal(&mut self, normal: [f32; 3]) {
+        self.deferred_pass.default_normal = normal;
     }
 }
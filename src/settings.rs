@@ -0,0 +1,75 @@
+use std::{fs, path::Path};
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{DEFAULT_RESOLUTION, RESOLUTION_PRESETS},
+    gfx::pass::present_pass::{CrtFilter, ScaleMode},
+    renderer::RendererState,
+};
+
+/*
+   Player settings, saved next to the game as json whenever they change.
+   Anything missing from the file uses its default, so older settings files keep working as settings are added.
+*/
+
+pub const SETTINGS_PATH: &str = "settings.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub crt_filter: bool,
+    // usually one of RESOLUTION_PRESETS
+    pub resolution: (u32, u32),
+    // how the internal resolution is fitted to the window
+    pub scale_mode: ScaleMode,
+    // smooths the edges between scaled up pixels instead of leaving them nearest neighbour
+    pub sharp_bilinear: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            crt_filter: false,
+            resolution: DEFAULT_RESOLUTION,
+            scale_mode: ScaleMode::default(),
+            sharp_bilinear: false,
+        }
+    }
+}
+
+impl Settings {
+    // falls back to the defaults if the file doesn't exist yet or can't be read
+    pub fn load<P: AsRef<Path>>(path: P) -> Settings {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Settings::default();
+        }
+        match fs::read_to_string(path).map_err(Error::from).and_then(|json| Ok(serde_json::from_str(&json)?)) {
+            Result::Ok(settings) => settings,
+            Err(err) => {
+                log::warn!("couldn't load settings from {}, using the defaults: {}", path.display(), err);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // moves on to the next resolution preset, or the first if the current resolution isn't one
+    pub fn next_resolution(&mut self) {
+        let next = RESOLUTION_PRESETS.iter().position(|&preset| preset == self.resolution).map_or(0, |index| index + 1);
+        self.resolution = RESOLUTION_PRESETS[next % RESOLUTION_PRESETS.len()];
+    }
+
+    pub fn apply(&self, renderer: &mut RendererState) {
+        renderer.set_crt_filter(self.crt_filter.then(CrtFilter::default));
+        renderer.set_internal_resolution(self.resolution);
+        renderer.set_scale_mode(self.scale_mode);
+        renderer.set_sharp_bilinear(self.sharp_bilinear);
+    }
+}
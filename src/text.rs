@@ -0,0 +1,300 @@
+use anyhow::{anyhow, Result};
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use crate::{
+    gfx::{context::PresentTarget, pass::overlay_pass::{OverlayOptions, Space}},
+    renderer::RendererState,
+    texture::{Texture, TextureCreator},
+    texture_atlas::{Rect, TextureRegion},
+};
+
+/*
+   Bitmap fonts can come from a grid of equally sized glyphs in a texture atlas region, or from a BMFont text .fnt file.
+
+   Layout happens in pixels with y going down from the top of the first line, and is converted to world or screen space when drawn.
+*/
+
+#[derive(Clone, Copy, Debug)]
+pub struct Glyph {
+    pub src: Rect,
+    // from the pen position to the top left of the glyph
+    pub offset: (f32, f32),
+    pub advance: f32,
+}
+
+pub struct BitmapFont {
+    pub texture: Arc<Texture>,
+    pub line_height: f32,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    pub align: Align,
+    // lines are wrapped at spaces to fit, words longer than this are left on their own line
+    pub max_width: Option<f32>,
+    pub color: [f32; 4],
+    // extra pixels between lines
+    pub line_spacing: f32,
+}
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            align: Align::Left,
+            max_width: None,
+            color: [1.0, 1.0, 1.0, 1.0],
+            line_spacing: 0.0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum TextTarget {
+    // drawn into the deferred pass, so the font has to be on the same sheet as every other lit sprite.
+    // fonts loaded from .fnt files have their own texture, so they can only be drawn as overlays
+    Lit,
+    Overlay(OverlayOptions),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PositionedGlyph {
+    // top left of the glyph, relative to the top left of the text with y going down
+    pub position: (f32, f32),
+    pub src: Rect,
+}
+
+#[allow(dead_code)]
+impl BitmapFont {
+    // glyphs are read left to right, top to bottom from the region, one per character of charset
+    pub fn from_grid(region: &TextureRegion, glyph_size: (u32, u32), charset: &str) -> Result<BitmapFont> {
+        let columns = region.src.width / glyph_size.0;
+        let rows = region.src.height / glyph_size.1;
+        if charset.chars().count() > (columns * rows) as usize {
+            return Err(anyhow!("font grid only has room for {} glyphs", columns * rows));
+        }
+        let glyphs = charset
+            .chars()
+            .enumerate()
+            .map(|(index, character)| {
+                let (column, row) = (index as u32 % columns, index as u32 / columns);
+                (character, Glyph {
+                    src: Rect {
+                        x: region.src.x + column * glyph_size.0,
+                        y: region.src.y + row * glyph_size.1,
+                        width: glyph_size.0,
+                        height: glyph_size.1,
+                    },
+                    offset: (0.0, 0.0),
+                    advance: glyph_size.0 as f32,
+                })
+            })
+            .collect();
+        Ok(BitmapFont {
+            texture: region.texture.clone(),
+            line_height: glyph_size.1 as f32,
+            glyphs,
+            kerning: HashMap::new(),
+        })
+    }
+
+    // only the text format with a single page is supported
+    pub fn load_fnt<P: AsRef<Path>>(path: P, texture_creator: &TextureCreator) -> Result<BitmapFont> {
+        let source = std::fs::read_to_string(&path)?;
+        let mut line_height = None;
+        let mut page = None;
+        let mut glyphs = HashMap::new();
+        let mut kerning = HashMap::new();
+        for line in source.lines() {
+            let mut parts = line.split_whitespace();
+            let Some(tag) = parts.next() else {
+                continue;
+            };
+            let fields: HashMap<&str, &str> = parts
+                .filter_map(|part| part.split_once('='))
+                .collect();
+            let number = |name: &str| -> Result<i32> {
+                fields
+                    .get(name)
+                    .ok_or(anyhow!("{tag} is missing {name}"))?
+                    .parse::<i32>()
+                    .map_err(|e| anyhow!("{tag} has an invalid {name}: {e}"))
+            };
+            match tag {
+                "common" => {
+                    if number("pages")? != 1 {
+                        return Err(anyhow!("only fonts with a single page are supported"));
+                    }
+                    line_height = Some(number("lineHeight")? as f32);
+                }
+                "page" => {
+                    let file = fields.get("file").ok_or(anyhow!("page is missing file"))?;
+                    page = Some(file.trim_matches('"').to_string());
+                }
+                "char" => {
+                    let character = char::from_u32(number("id")? as u32).ok_or(anyhow!("invalid character id"))?;
+                    glyphs.insert(character, Glyph {
+                        src: Rect {
+                            x: number("x")? as u32,
+                            y: number("y")? as u32,
+                            width: number("width")? as u32,
+                            height: number("height")? as u32,
+                        },
+                        offset: (number("xoffset")? as f32, number("yoffset")? as f32),
+                        advance: number("xadvance")? as f32,
+                    });
+                }
+                "kerning" => {
+                    let first = char::from_u32(number("first")? as u32).ok_or(anyhow!("invalid character id"))?;
+                    let second = char::from_u32(number("second")? as u32).ok_or(anyhow!("invalid character id"))?;
+                    kerning.insert((first, second), number("amount")? as f32);
+                }
+                _ => (),
+            }
+        }
+        let page = page.ok_or(anyhow!("font has no page"))?;
+        let texture = texture_creator.load(path.as_ref().with_file_name(page))?;
+        Ok(BitmapFont {
+            texture: Arc::new(texture),
+            line_height: line_height.ok_or(anyhow!("font has no common line"))?,
+            glyphs,
+            kerning,
+        })
+    }
+
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs.get(&character)
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kerning.get(&(first, second)).copied().unwrap_or(0.0)
+    }
+
+    // width of a single line of text, ignoring newlines
+    pub fn measure(&self, text: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for character in text.chars() {
+            if let Some(previous) = previous {
+                width += self.kerning(previous, character);
+            }
+            width += self.glyph(character).map(|glyph| glyph.advance).unwrap_or(0.0);
+            previous = Some(character);
+        }
+        width
+    }
+
+    fn wrap<'a>(&self, text: &'a str, max_width: Option<f32>) -> Vec<&'a str> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let Some(max_width) = max_width else {
+                lines.push(paragraph);
+                continue;
+            };
+            let mut start = 0;
+            let mut end = 0;
+            for (index, _) in paragraph.match_indices(' ').chain([(paragraph.len(), "")]) {
+                if end > start && self.measure(&paragraph[start..index]) > max_width {
+                    lines.push(&paragraph[start..end]);
+                    start = end + 1;
+                }
+                end = index;
+            }
+            lines.push(&paragraph[start.min(paragraph.len())..]);
+        }
+        lines
+    }
+
+    pub fn layout(&self, text: &str, style: &TextStyle) -> Vec<PositionedGlyph> {
+        let lines = self.wrap(text, style.max_width);
+        let block_width = style.max_width.unwrap_or_else(|| {
+            lines
+                .iter()
+                .map(|line| self.measure(line))
+                .fold(0.0, f32::max)
+        });
+        let mut positioned = Vec::new();
+        for (row, line) in lines.iter().enumerate() {
+            let mut pen = match style.align {
+                Align::Left => 0.0,
+                Align::Center => ((block_width - self.measure(line)) / 2.0).floor(),
+                Align::Right => block_width - self.measure(line),
+            };
+            let top = row as f32 * (self.line_height + style.line_spacing);
+            let mut previous = None;
+            for character in line.chars() {
+                if let Some(previous) = previous {
+                    pen += self.kerning(previous, character);
+                }
+                previous = Some(character);
+                let Some(glyph) = self.glyph(character) else {
+                    continue;
+                };
+                if glyph.src.width > 0 && glyph.src.height > 0 {
+                    positioned.push(PositionedGlyph {
+                        position: (pen + glyph.offset.0, top + glyph.offset.1),
+                        src: glyph.src,
+                    });
+                }
+                pen += glyph.advance;
+            }
+        }
+        positioned
+    }
+
+    // position is the top left of the text, in whichever space the target draws in.
+    // fails without drawing anything if the target is Lit and the font isn't on the deferred pass's sheet
+    pub fn draw<T: PresentTarget>(
+        &self,
+        renderer: &mut RendererState<T>,
+        position: (f32, f32),
+        text: &str,
+        style: &TextStyle,
+        target: TextTarget,
+    ) -> Result<()> {
+        if matches!(target, TextTarget::Lit) && !renderer.is_on_sheet(&self.texture) {
+            return Err(anyhow!("lit text has to use a font on the sprite sheet, draw it as an overlay instead"));
+        }
+        for glyph in self.layout(text, style) {
+            let image = TextureRegion {
+                texture: self.texture.clone(),
+                src: glyph.src,
+                pivot: (0.0, 0.0),
+                nine_slice: None,
+            };
+            let size = (glyph.src.width as f32, glyph.src.height as f32);
+            match target {
+                TextTarget::Lit => renderer.draw_tinted_sprite(
+                    (
+                        position.0 + glyph.position.0,
+                        position.1 - glyph.position.1 - size.1,
+                        0.0,
+                    ),
+                    size,
+                    style.color,
+                    image,
+                )?,
+                TextTarget::Overlay(options) => {
+                    let glyph_position = match options.space {
+                        Space::World => (position.0 + glyph.position.0, position.1 - glyph.position.1 - size.1),
+                        Space::Screen => (position.0 + glyph.position.0, position.1 + glyph.position.1),
+                    };
+                    renderer.draw_overlay_sprite_sized(glyph_position, size, image, OverlayOptions {
+                        tint: style.color,
+                        ..options
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -8,12 +8,17 @@ use crate::texture::Texture;
 pub struct TextureRegion {
     pub texture: Arc<Texture>,
     pub src: Rect,
+    // the point that gets placed at a sprite's position, in pixels from its bottom left with y going up
+    pub pivot: (f32, f32),
+    pub nine_slice: Option<Insets>,
 }
 
 impl std::fmt::Debug for TextureRegion {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.debug_struct("TextureRegion")
             .field("src", &self.src)
+            .field("pivot", &self.pivot)
+            .field("nine_slice", &self.nine_slice)
             .finish()
     }
 }
@@ -23,12 +28,16 @@ pub struct DeferredTextureRegion {
     pub texture: Arc<Texture>,
     pub albedo: Rect,
     pub normal: Rect,
+    pub pivot: (f32, f32),
+    pub nine_slice: Option<Insets>,
 }
 impl std::fmt::Debug for DeferredTextureRegion {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.debug_struct("NormalPairTextureRegion")
             .field("albedo", &self.albedo)
             .field("normal", &self.normal)
+            .field("pivot", &self.pivot)
+            .field("nine_slice", &self.nine_slice)
             .finish()
     }
 }
@@ -53,9 +62,10 @@ impl TextureAtlas {
         .map_err(|e| e.to_string())?;
 
         for (name, region) in raw_regions {
-            atlas
-                .regions
-                .insert(name, Rc::new(region.set_image(atlas.image.clone(), 0, 0)));
+            let region = region
+                .set_image(atlas.image.clone(), 0, 0)
+                .map_err(|e| format!("{}: {}", name, e))?;
+            atlas.regions.insert(name, Rc::new(region));
         }
 
         println!("{:?}", atlas.regions);
@@ -67,22 +77,71 @@ impl TextureAtlas {
 }
 #[derive(Deserialize)]
 enum RawRegion {
-    Single(Rect),
-    NormalPair(Rect, Rect),
-    Animation(Rect, Vec<RawRegion>),
+    Single(RawSprite),
+    NormalPair(RawSprite, Rect),
+    Animation(Rect, Vec<RawRegion>, #[serde(default)] RawAnimationMeta),
     Atlas(Rect, HashMap<String, RawRegion>),
 }
 
+// the pivot is in pixels from the top left of the image, like src, and defaults to the bottom left
+#[derive(Deserialize)]
+struct RawSprite {
+    #[serde(flatten)]
+    src: Rect,
+    #[serde(default)]
+    pivot: Option<(f32, f32)>,
+    #[serde(default)]
+    nine_slice: Option<Insets>,
+}
+
+impl RawSprite {
+    fn pivot(&self) -> (f32, f32) {
+        self.pivot
+            .map(|(x, y)| (x, self.src.height as f32 - y))
+            .unwrap_or((0.0, 0.0))
+    }
+    // the nine slice borders, as long as they fit inside the sprite
+    fn nine_slice(&self) -> Result<Option<Insets>, String> {
+        match self.nine_slice {
+            Some(insets) if !insets.fits(self.src) => Err(format!(
+                "nine slice insets {:?} don't fit in a {}x{} sprite",
+                insets, self.src.width, self.src.height
+            )),
+            insets => Ok(insets),
+        }
+    }
+}
+
+// durations are in milliseconds, and older atlases without any meta get the default
+#[derive(Deserialize, Default)]
+struct RawAnimationMeta {
+    #[serde(default)]
+    durations: Vec<u32>,
+    #[serde(default)]
+    tags: HashMap<String, Tag>,
+}
+
 impl RawRegion {
-    fn set_image(self, texture: Arc<Texture>, x_offset: u32, y_offset: u32) -> Region {
-        match self {
-            Self::Single(mut src) => Region::Single({
+    fn set_image(self, texture: Arc<Texture>, x_offset: u32, y_offset: u32) -> Result<Region, String> {
+        Ok(match self {
+            Self::Single(sprite) => Region::Single({
+                let pivot = sprite.pivot();
+                let nine_slice = sprite.nine_slice()?;
+                let mut src = sprite.src;
                 src.x += x_offset;
                 src.y += y_offset;
 
-                TextureRegion { texture, src }
+                TextureRegion {
+                    texture,
+                    src,
+                    pivot,
+                    nine_slice,
+                }
             }),
-            Self::NormalPair(mut albedo, mut normal) => Region::NormalPair({
+            Self::NormalPair(sprite, mut normal) => Region::NormalPair({
+                let pivot = sprite.pivot();
+                let nine_slice = sprite.nine_slice()?;
+                let mut albedo = sprite.src;
                 albedo.x += x_offset;
                 albedo.y += y_offset;
                 normal.x += x_offset;
@@ -92,32 +151,54 @@ impl RawRegion {
                     texture,
                     albedo,
                     normal,
+                    pivot,
+                    nine_slice,
                 }
             }),
-            Self::Animation(mut src, raw_frames) => {
+            Self::Animation(mut src, raw_frames, meta) => {
                 src.x += x_offset;
                 src.y += y_offset;
                 let mut frames = Vec::new();
                 for frame in raw_frames {
-                    frames.push(frame.set_image(texture.clone(), src.x, src.y));
+                    frames.push(frame.set_image(texture.clone(), src.x, src.y)?);
+                }
+                // clips are cut out by tag, so an empty or out of range tag would make an animation with no frames
+                for (name, tag) in meta.tags.iter() {
+                    if tag.from > tag.to || tag.to >= frames.len() {
+                        return Err(format!(
+                            "tag {} covers frames {} to {}, but there are {} frames",
+                            name,
+                            tag.from,
+                            tag.to,
+                            frames.len()
+                        ));
+                    }
                 }
 
-                Region::Animation(frames)
+                Region::Animation(Animation {
+                    frames,
+                    durations: meta
+                        .durations
+                        .into_iter()
+                        .map(|duration| duration as f32 / 1000.0)
+                        .collect(),
+                    tags: meta.tags,
+                })
             }
             Self::Atlas(mut src, raw_atlas) => {
                 src.x += x_offset;
                 src.y += y_offset;
                 let mut atlas = HashMap::new();
                 for (name, region) in raw_atlas {
-                    atlas.insert(
-                        name,
-                        region.set_image(texture.clone(), x_offset + src.x, y_offset + src.y),
-                    );
+                    let region = region
+                        .set_image(texture.clone(), x_offset + src.x, y_offset + src.y)
+                        .map_err(|e| format!("{}: {}", name, e))?;
+                    atlas.insert(name, region);
                 }
 
                 Region::Atlas(atlas)
             }
-        }
+        })
     }
 }
 #[derive(Deserialize, Debug, Clone, Copy)]
@@ -128,11 +209,72 @@ pub struct Rect {
     pub height: u32,
 }
 
+// nine slice borders, in pixels in from each edge of the image
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Insets {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl Insets {
+    // true if the borders on each axis don't overlap in src, so there's no negative middle
+    pub fn fits(&self, src: Rect) -> bool {
+        self.left as u64 + self.right as u64 <= src.width as u64
+            && self.top as u64 + self.bottom as u64 <= src.height as u64
+    }
+}
+
+// an inclusive range of frames
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag {
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub frames: Vec<Region>,
+    // in seconds, one per frame, or empty if the atlas doesn't say how long frames last
+    pub durations: Vec<f32>,
+    pub tags: HashMap<String, Tag>,
+}
+
+impl Animation {
+    // the frames covered by tag, with any tags that fit inside it moved to match
+    pub fn clip(&self, tag: &str) -> Option<Animation> {
+        let range = *self.tags.get(tag)?;
+        let frames = self.frames.get(range.from..=range.to)?.to_vec();
+        let durations = self
+            .durations
+            .get(range.from..=range.to)
+            .map(|durations| durations.to_vec())
+            .unwrap_or_default();
+        let tags = self
+            .tags
+            .iter()
+            .filter(|(_, inner)| inner.from >= range.from && inner.to <= range.to)
+            .map(|(name, inner)| {
+                (name.clone(), Tag {
+                    from: inner.from - range.from,
+                    to: inner.to - range.from,
+                })
+            })
+            .collect();
+        Some(Animation {
+            frames,
+            durations,
+            tags,
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Region {
     Single(TextureRegion),
     NormalPair(DeferredTextureRegion),
-    Animation(Vec<Region>),
+    Animation(Animation),
     Atlas(HashMap<String, Region>),
 }
 
@@ -152,9 +294,9 @@ impl Region {
             panic!("{reason}: {self:?}");
         }
     }
-    pub fn expect_animation(&self, reason: &'static str) -> Vec<Region> {
-        if let Self::Animation(frames) = self {
-            frames.to_owned()
+    pub fn expect_animation(&self, reason: &'static str) -> Animation {
+        if let Self::Animation(animation) = self {
+            animation.to_owned()
         } else {
             panic!("{reason}: {self:?}");
         }
@@ -180,13 +322,24 @@ impl Region {
             panic!("unwrap_single failed, was given: {self:?}");
         }
     }
-    pub fn unwrap_animation(&self) -> Vec<Region> {
-        if let Self::Animation(frames) = self {
-            frames.to_owned()
+    pub fn unwrap_animation(&self) -> Animation {
+        if let Self::Animation(animation) = self {
+            animation.to_owned()
         } else {
             panic!("unwrap_animation failed, was given: {self:?}");
         }
     }
+    pub fn get_clip(&self, tag: &str) -> Option<Animation> {
+        if let Self::Animation(animation) = self {
+            animation.clip(tag)
+        } else {
+            None
+        }
+    }
+    pub fn unwrap_clip(&self, tag: &str) -> Animation {
+        self.get_clip(tag)
+            .unwrap_or_else(|| panic!("unwrap_clip failed to find {tag}, was given: {self:?}"))
+    }
     pub fn unwrap_atlas(&self) -> HashMap<String, Region> {
         if let Self::Atlas(atlas) = self {
             atlas.to_owned()
@@ -0,0 +1,300 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{fs::File, io::BufReader, path::Path};
+
+use super::{Room, Tile};
+use crate::gfx::pass::deferred_pass::SpriteTransform;
+
+/*
+   Autotiling picks which variant of a tile to draw from the tiles around it.
+
+   Each cell gets a neighbour mask with a bit set for every neighbour that is the same tile. The rules are tried in order,
+   and the first rule whose connected directions match the mask (ignoring any directions the rule doesn't care about) is used.
+
+   With the Eight neighbourhood, a corner only counts as connected if both of the sides next to it are connected too,
+   which keeps the number of distinct masks down to the usual 47 for blob tilesets.
+*/
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    Four,
+    Eight,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    N,
+    E,
+    S,
+    W,
+    NE,
+    SE,
+    SW,
+    NW,
+}
+
+impl Direction {
+    const SIDES: [Direction; 4] = [Direction::N, Direction::E, Direction::S, Direction::W];
+    const CORNERS: [Direction; 4] = [Direction::NE, Direction::SE, Direction::SW, Direction::NW];
+
+    pub fn bit(self) -> u8 {
+        match self {
+            Direction::N => 1,
+            Direction::E => 1 << 1,
+            Direction::S => 1 << 2,
+            Direction::W => 1 << 3,
+            Direction::NE => 1 << 4,
+            Direction::SE => 1 << 5,
+            Direction::SW => 1 << 6,
+            Direction::NW => 1 << 7,
+        }
+    }
+    // offsets are in room coordinates, so north is -y
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::N => (0, -1),
+            Direction::E => (1, 0),
+            Direction::S => (0, 1),
+            Direction::W => (-1, 0),
+            Direction::NE => (1, -1),
+            Direction::SE => (1, 1),
+            Direction::SW => (-1, 1),
+            Direction::NW => (-1, -1),
+        }
+    }
+    fn adjacent_sides(self) -> Option<(Direction, Direction)> {
+        match self {
+            Direction::NE => Some((Direction::N, Direction::E)),
+            Direction::SE => Some((Direction::S, Direction::E)),
+            Direction::SW => Some((Direction::S, Direction::W)),
+            Direction::NW => Some((Direction::N, Direction::W)),
+            _ => None,
+        }
+    }
+}
+
+fn to_mask(directions: &[Direction]) -> u8 {
+    directions.iter().fold(0, |mask, dir| mask | dir.bit())
+}
+
+// rotation is in counter clockwise quarter turns, applied after flipping
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TileTransform {
+    #[serde(default)]
+    pub rotation: u8,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+}
+
+impl TileTransform {
+    pub fn sprite_transform(self) -> SpriteTransform {
+        SpriteTransform {
+            rotation: self.rotation as f32 * std::f32::consts::FRAC_PI_2,
+            scale: (if self.flip_x { -1.0 } else { 1.0 }, if self.flip_y { -1.0 } else { 1.0 }),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AutotileRule {
+    pub variant: String,
+    pub transform: TileTransform,
+    mask: u8,
+    care: u8,
+}
+
+impl AutotileRule {
+    pub fn matches(&self, mask: u8) -> bool {
+        mask & self.care == self.mask
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlacedTile {
+    pub x: usize,
+    pub y: usize,
+    pub variant: String,
+    pub transform: TileTransform,
+}
+
+pub struct AutotileRules {
+    pub tile: Tile,
+    pub neighbourhood: Neighbourhood,
+    // whether cells outside of the room count as connected
+    pub edges_connect: bool,
+    rules: Vec<AutotileRule>,
+}
+
+#[derive(Deserialize)]
+struct RawAutotileRule {
+    variant: String,
+    connected: Vec<Direction>,
+    #[serde(default)]
+    ignore: Vec<Direction>,
+    #[serde(default, flatten)]
+    transform: TileTransform,
+}
+
+#[derive(Deserialize)]
+struct RawAutotileRules {
+    tile: Tile,
+    neighbourhood: Neighbourhood,
+    #[serde(default)]
+    edges_connect: bool,
+    rules: Vec<RawAutotileRule>,
+}
+
+#[allow(dead_code)]
+impl AutotileRules {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AutotileRules> {
+        AutotileRules::from_raw(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+    pub fn parse(json: &str) -> Result<AutotileRules> {
+        AutotileRules::from_raw(serde_json::from_str(json)?)
+    }
+    fn from_raw(raw: RawAutotileRules) -> Result<AutotileRules> {
+        let neighbourhood_mask = match raw.neighbourhood {
+            Neighbourhood::Four => to_mask(&Direction::SIDES),
+            Neighbourhood::Eight => to_mask(&Direction::SIDES) | to_mask(&Direction::CORNERS),
+        };
+        let rules = raw
+            .rules
+            .into_iter()
+            .map(|rule| {
+                let mask = to_mask(&rule.connected);
+                if mask & !neighbourhood_mask != 0 {
+                    return Err(anyhow!(
+                        "rule for {} uses corners in a Four neighbourhood",
+                        rule.variant
+                    ));
+                }
+                if rule.transform.rotation > 3 {
+                    return Err(anyhow!(
+                        "rule for {} has a rotation of more than 3 quarter turns",
+                        rule.variant
+                    ));
+                }
+                let care = neighbourhood_mask & !to_mask(&rule.ignore);
+                Ok(AutotileRule {
+                    variant: rule.variant,
+                    transform: rule.transform,
+                    mask: mask & care,
+                    care,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(AutotileRules {
+            tile: raw.tile,
+            neighbourhood: raw.neighbourhood,
+            edges_connect: raw.edges_connect,
+            rules,
+        })
+    }
+
+    pub fn mask(&self, room: &Room, x: usize, y: usize) -> u8 {
+        let connected = |dir: Direction| {
+            let (dx, dy) = dir.offset();
+            match room.get(x as isize + dx, y as isize + dy) {
+                Some(tile) => tile == self.tile,
+                None => self.edges_connect,
+            }
+        };
+        let mut mask = 0;
+        for dir in Direction::SIDES {
+            if connected(dir) {
+                mask |= dir.bit();
+            }
+        }
+        if self.neighbourhood == Neighbourhood::Eight {
+            for dir in Direction::CORNERS {
+                let (a, b) = dir.adjacent_sides().expect("corners always have two sides");
+                if mask & a.bit() != 0 && mask & b.bit() != 0 && connected(dir) {
+                    mask |= dir.bit();
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn resolve(&self, mask: u8) -> Option<&AutotileRule> {
+        self.rules.iter().find(|rule| rule.matches(mask))
+    }
+
+    pub fn apply(&self, room: &Room) -> Vec<PlacedTile> {
+        let mut placed = Vec::new();
+        for y in 0..room.height() {
+            for x in 0..room.width() {
+                if room.get(x as isize, y as isize) != Some(self.tile) {
+                    continue;
+                }
+                let mask = self.mask(room, x, y);
+                match self.resolve(mask) {
+                    Some(rule) => placed.push(PlacedTile {
+                        x,
+                        y,
+                        variant: rule.variant.clone(),
+                        transform: rule.transform,
+                    }),
+                    None => log::warn!("no autotile rule for {:?} with mask {mask:#010b}", self.tile),
+                }
+            }
+        }
+        placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall_rules() -> AutotileRules {
+        AutotileRules::load(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/rooms/wall_autotile.json")).unwrap()
+    }
+
+    fn placed_at(placed: &[PlacedTile], x: usize, y: usize) -> &PlacedTile {
+        placed.iter().find(|tile| tile.x == x && tile.y == y).unwrap()
+    }
+
+    #[test]
+    fn corners_are_flipped_to_face_the_walls_they_join() {
+        let room = Room::parse("0 0 0\n0 1 0\n0 0 0").unwrap();
+        let placed = wall_rules().apply(&room);
+        for (x, y, flip_x, flip_y) in [(0, 0, false, false), (2, 0, true, false), (0, 2, false, true), (2, 2, true, true)] {
+            let tile = placed_at(&placed, x, y);
+            assert_eq!(tile.variant, "corners", "({}, {})", x, y);
+            assert_eq!(tile.transform, TileTransform { rotation: 0, flip_x, flip_y }, "({}, {})", x, y);
+        }
+        assert_eq!(placed_at(&placed, 1, 0).variant, "horizontal");
+        assert_eq!(placed_at(&placed, 0, 1).variant, "vertical");
+    }
+
+    #[test]
+    fn corner_masks_only_use_the_sides() {
+        let rules = wall_rules();
+        let room = Room::parse("0 0\n0 1").unwrap();
+        assert_eq!(rules.mask(&room, 0, 0), Direction::E.bit() | Direction::S.bit());
+        assert_eq!(rules.mask(&room, 1, 0), Direction::W.bit());
+        assert_eq!(rules.resolve(Direction::N.bit() | Direction::E.bit() | Direction::S.bit()).unwrap().variant, "all");
+    }
+
+    #[test]
+    fn eight_neighbourhood_corners_need_both_adjacent_sides() {
+        let rules = AutotileRules::parse(
+            r#"{ "tile": "Wall", "neighbourhood": "Eight", "rules": [{ "variant": "any", "connected": [], "ignore": ["N", "E", "S", "W", "NE", "SE", "SW", "NW"] }] }"#,
+        )
+        .unwrap();
+        // the south east corner is a wall, but only one of the sides next to it is
+        let room = Room::parse("0 0\n1 0").unwrap();
+        assert_eq!(rules.mask(&room, 0, 0), Direction::E.bit());
+        let room = Room::parse("0 0\n0 0").unwrap();
+        assert_eq!(rules.mask(&room, 0, 0), Direction::E.bit() | Direction::S.bit() | Direction::SE.bit());
+    }
+
+    #[test]
+    fn corners_are_rejected_in_a_four_neighbourhood() {
+        let rules = AutotileRules::parse(r#"{ "tile": "Wall", "neighbourhood": "Four", "rules": [{ "variant": "corner", "connected": ["NE"] }] }"#);
+        assert!(rules.is_err());
+    }
+}
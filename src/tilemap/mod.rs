@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+pub mod autotile;
+
+pub const TILE_SIZE: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Deserialize)]
+pub enum Tile {
+    Wall,
+    Floor,
+}
+
+impl Tile {
+    fn from_id(id: u8) -> Option<Tile> {
+        match id {
+            0 => Some(Tile::Wall),
+            1 => Some(Tile::Floor),
+            _ => None,
+        }
+    }
+}
+
+// rooms are stored as whitespace separated tile ids, one row per line, with the first line being the top of the room.
+pub struct Room {
+    width: usize,
+    height: usize,
+    cells: Vec<Tile>,
+}
+
+#[allow(dead_code)]
+impl Room {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Room> {
+        Room::parse(&std::fs::read_to_string(path)?)
+    }
+    pub fn parse(source: &str) -> Result<Room> {
+        let mut width = None;
+        let mut cells = Vec::new();
+        let mut height = 0;
+        for line in source.lines().filter(|line| !line.trim().is_empty()) {
+            let row = line
+                .split_whitespace()
+                .map(|id| {
+                    let id = id.parse::<u8>()?;
+                    Tile::from_id(id).ok_or(anyhow!("unknown tile id {id}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            match width {
+                None => width = Some(row.len()),
+                Some(width) if width != row.len() => {
+                    return Err(anyhow!("room rows must all have the same length"))
+                }
+                _ => (),
+            }
+            cells.extend(row);
+            height += 1;
+        }
+        Ok(Room {
+            width: width.unwrap_or(0),
+            height,
+            cells,
+        })
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    // x goes right and y goes down, matching the layout of the room file
+    pub fn get(&self, x: isize, y: isize) -> Option<Tile> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some(self.cells[y as usize * self.width + x as usize])
+    }
+    // world position of the bottom left corner of a cell, with the room's top left corner at the origin
+    pub fn cell_position(&self, x: usize, y: usize) -> (f32, f32) {
+        (x as f32 * TILE_SIZE, -((y + 1) as f32) * TILE_SIZE)
+    }
+}
@@ -0,0 +1,41 @@
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) tex_coords: vec2f,
+}
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4f(model.position, 1.0);
+    return out;
+}
+
+struct Vignette {
+    color: vec4f,
+    // intensity, radius, softness, aspect ratio
+    shape: vec4f,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(1) @binding(0)
+var<uniform> vignette: Vignette;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    // distance from the centre, where 1 is a corner, stretched so the falloff is round on a wide screen
+    let from_center = (in.tex_coords - vec2f(0.5, 0.5)) * vec2f(vignette.shape.w, 1.0);
+    let distance = length(from_center) / length(vec2f(0.5 * vignette.shape.w, 0.5));
+    let amount = smoothstep(vignette.shape.y, vignette.shape.y + vignette.shape.z, distance) * vignette.shape.x;
+    return vec4f(mix(color.rgb, vignette.color.rgb, amount), color.a);
+}
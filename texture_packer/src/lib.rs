@@ -4,7 +4,7 @@ use std::fs::File;
 use std::path::{Path, PathBuf};
 
 use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, RgbaImage};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /*
    This tool will support converting a sets of png images into texture atlases.
@@ -196,14 +196,148 @@ impl<T: GenericImageView> From<T> for Rect {
     }
 }
 
+// a single image's rect, with the pivot in pixels from its top left if pivots.json gives it one,
+// and its nine slice borders if nine_slice.json does
+#[derive(Debug, Serialize)]
+struct Sprite {
+    #[serde(flatten)]
+    rect: Rect,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pivot: Option<(f32, f32)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nine_slice: Option<Insets>,
+}
+
 #[derive(Debug, Serialize)]
 enum Region {
-    Single(Rect),
-    NormalPair(Rect, Rect),
-    Animation(Rect, Vec<Region>),
+    Single(Sprite),
+    NormalPair(Sprite, Rect),
+    Animation(Rect, Vec<Region>, AnimationMeta),
     Atlas(Rect, HashMap<String, Region>),
 }
 
+/*
+   Frame durations and tags come from an optional animation.json in the animation's folder, in the same format that gets written to the atlas:
+
+   {
+       "durations": [100, 100, 200],
+       "tags": { "walk": { "from": 0, "to": 2 } }
+   }
+
+   durations are in milliseconds, one per frame in frame number order, and can be left out to let the game pick a speed.
+   tag ranges are inclusive frame indices.
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AnimationMeta {
+    #[serde(default)]
+    durations: Vec<u32>,
+    #[serde(default)]
+    tags: HashMap<String, Tag>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Tag {
+    from: usize,
+    to: usize,
+}
+
+impl AnimationMeta {
+    fn load<P: AsRef<Path>>(folder: P, frame_count: usize) -> Result<AnimationMeta, Box<dyn Error>> {
+        let path = folder.as_ref().join("animation.json");
+        if !path.exists() {
+            return Ok(AnimationMeta::default());
+        }
+        let meta: AnimationMeta = serde_json::from_reader(File::open(&path)?)?;
+        if !meta.durations.is_empty() && meta.durations.len() != frame_count {
+            return Err(format!(
+                "{} has {} durations but the animation has {frame_count} frames",
+                path.display(),
+                meta.durations.len()
+            )
+            .into());
+        }
+        for (name, tag) in meta.tags.iter() {
+            if tag.from > tag.to || tag.to >= frame_count {
+                return Err(format!("tag {name} in {} is out of range", path.display()).into());
+            }
+        }
+        Ok(meta)
+    }
+}
+
+/*
+   Pivots come from an optional pivots.json in the folder the images are in, and apply to the images directly in that folder:
+
+   {
+       "default": [0.5, 1.0],
+       "sprites": { "zombie": [0.5, 0.9] }
+   }
+
+   pivots are fractions of the image's size from its top left, so [0.5, 1.0] is the bottom centre, and get converted to pixels for the atlas.
+   normal pairs use the name of their color image. images without a pivot are placed by their bottom left in the game.
+*/
+#[derive(Debug, Default, Deserialize)]
+struct Pivots {
+    #[serde(default)]
+    default: Option<(f32, f32)>,
+    #[serde(default)]
+    sprites: HashMap<String, (f32, f32)>,
+}
+
+/*
+   Nine slice borders come from an optional nine_slice.json next to pivots.json, in pixels from each edge:
+
+   {
+       "panel": { "left": 4, "right": 4, "top": 3, "bottom": 5 }
+   }
+
+   they're written to the atlas as they are, and let the game stretch or tile the middle of the image without touching the corners.
+*/
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Insets {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+#[derive(Debug, Default)]
+struct SpriteMeta {
+    pivots: Pivots,
+    nine_slices: HashMap<String, Insets>,
+}
+
+impl SpriteMeta {
+    fn load<P: AsRef<Path>>(folder: P) -> Result<SpriteMeta, Box<dyn Error>> {
+        let mut meta = SpriteMeta::default();
+        let pivots = folder.as_ref().join("pivots.json");
+        if pivots.exists() {
+            meta.pivots = serde_json::from_reader(File::open(&pivots)?)?;
+        }
+        let nine_slices = folder.as_ref().join("nine_slice.json");
+        if nine_slices.exists() {
+            meta.nine_slices = serde_json::from_reader(File::open(&nine_slices)?)?;
+        }
+        Ok(meta)
+    }
+
+    fn sprite(&self, name: &str, rect: Rect) -> Result<Sprite, Box<dyn Error>> {
+        let pivot = self
+            .pivots
+            .sprites
+            .get(name)
+            .or(self.pivots.default.as_ref())
+            .map(|pivot| (pivot.0 * rect.width as f32, pivot.1 * rect.height as f32));
+        let nine_slice = self.nine_slices.get(name).copied();
+        if let Some(insets) = nine_slice {
+            if insets.left + insets.right > rect.width || insets.top + insets.bottom > rect.height {
+                return Err(format!("nine slice borders for {name} are bigger than the image").into());
+            }
+        }
+        Ok(Sprite { rect, pivot, nine_slice })
+    }
+}
+
 struct Strip {
     y: u32,
     height: u32,
@@ -220,7 +354,7 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
     println!("creating atlas from {}", input_folder.as_ref().as_os_str().to_str().unwrap());
 
     let subfolders = get_sub_directories(&input_folder)?;
-    let mut animations = HashMap::<String, Vec<Region>>::new();
+    let mut animations = HashMap::<String, (Vec<Region>, AnimationMeta)>::new();
     let mut atlases = HashMap::<String, HashMap<String, Region>>::new();
     for folder in subfolders {
         if folder
@@ -230,13 +364,14 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
             .expect("use utf file names")
             .contains("anim")
         {
+            // keyed by the name of the image create_animation saves, so add_image can find it again
             animations.insert(
-                folder
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_str()
-                    .expect("use utf file names")
-                    .to_string(),
+                String::from("anim_")
+                    + folder
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_str()
+                        .expect("use utf file names"),
                 create_animation(folder)?,
             );
         } else {
@@ -252,50 +387,7 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
         }
     }
 
-    let mut normal_pairs =
-        glob::glob(format!("{}/*.png", input_folder.as_ref().display()).as_str())?
-            .filter_map(|e| e.ok())
-            .filter_map(|path| {
-                let mut normal_path = path.clone();
-                normal_path.set_file_name(
-                    path.file_stem().unwrap().to_string_lossy().to_string() + "_normal.png",
-                );
-                if normal_path.exists() {
-                    Some((path, normal_path))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-
-    for (name, color, normal) in normal_pairs.iter().filter_map(|paths| {
-        if let (Ok(color), Ok(normal)) = (image::open(&paths.0), image::open(&paths.1)) {
-            Some((
-                paths
-                    .0
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_str()
-                    .expect("use utf file names")
-                    .to_string(),
-                color,
-                normal,
-            ))
-        } else {
-            None
-        }
-    }) {
-        if color.dimensions() != normal.dimensions() {
-            return Err("Normal map dimensions should match its pair's dimensions!".into());
-        }
-        let mut buffer = ImageBuffer::new(color.width(), color.height() * 2);
-        buffer.copy_from(&color, 0, 0)?;
-        buffer.copy_from(&normal, 0, color.height())?;
-        buffer.save(
-            PathBuf::from(input_folder.as_ref())
-                .join(String::from("normal_pair_") + &name + ".png"),
-        )?;
-    }
+    let normal_pairs = create_normal_pairs(&input_folder)?;
 
     let mut images = glob::glob(format!("{}/*.png", input_folder.as_ref().display()).as_str())?
         .filter_map(|e| e.ok())
@@ -325,7 +417,9 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
 
     images.sort_by_key(|img| (img.1.height(), img.1.width()));
     images.reverse();
+    let sprite_meta = SpriteMeta::load(&input_folder)?;
 
+    #[allow(clippy::too_many_arguments)]
     fn add_image(
         name: String,
         image: DynamicImage,
@@ -333,7 +427,8 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
         regions: &mut HashMap<String, Region>,
         strips: &mut Vec<Strip>,
         atlases: &mut HashMap<String, HashMap<String, Region>>,
-        animations: &mut HashMap<String, Vec<Region>>,
+        animations: &mut HashMap<String, (Vec<Region>, AnimationMeta)>,
+        sprite_meta: &SpriteMeta,
     ) -> Result<(), Box<dyn Error>> {
         println!("adding {name}");
         for strip in strips.iter_mut() {
@@ -342,13 +437,15 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
             }
             if strip.used_width + image.width() <= atlas.width() {
                 if name.starts_with("anim_") {
+                    let (frames, meta) = animations.remove(&name).expect(
+                        "images starting with anim_ should only be made by this tool",
+                    );
                     regions.insert(
                         name.strip_prefix("anim_").unwrap().to_string(),
                         Region::Animation(
                             Rect::new(strip.used_width, strip.y, image.width(), image.height()),
-                            animations.remove(&name).expect(
-                                "images starting with anim_ should only be made by this tool",
-                            ),
+                            frames,
+                            meta,
                         ),
                     );
                 } else if name.starts_with("sheet_") {
@@ -362,10 +459,14 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
                         ),
                     );
                 } else if name.starts_with("normal_pair_") {
+                    let pair_name = name.strip_prefix("normal_pair_").unwrap();
                     regions.insert(
-                        name.strip_prefix("normal_pair_").unwrap().to_string(),
+                        pair_name.to_string(),
                         Region::NormalPair(
-                            Rect::new(strip.used_width, strip.y, image.width(), image.height() / 2),
+                            sprite_meta.sprite(
+                                pair_name,
+                                Rect::new(strip.used_width, strip.y, image.width(), image.height() / 2),
+                            )?,
                             Rect::new(
                                 strip.used_width,
                                 strip.y + image.height() / 2,
@@ -377,12 +478,10 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
                 } else {
                     regions.insert(
                         name.clone(),
-                        Region::Single(Rect::new(
-                            strip.used_width,
-                            strip.y,
-                            image.width(),
-                            image.height(),
-                        )),
+                        Region::Single(sprite_meta.sprite(
+                            &name,
+                            Rect::new(strip.used_width, strip.y, image.width(), image.height()),
+                        )?),
                     );
                 }
                 atlas.copy_from(&image, strip.used_width, strip.y)?;
@@ -408,13 +507,15 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
             let strip = add_strip(strips, &image);
             if strip.used_width + image.width() <= atlas.width() {
                 if name.starts_with("anim_") {
+                    let (frames, meta) = animations.remove(&name).expect(
+                        "images starting with anim_ should only be made by this tool",
+                    );
                     regions.insert(
                         name.strip_prefix("anim_").unwrap().to_string(),
                         Region::Animation(
                             Rect::new(strip.used_width, strip.y, image.width(), image.height()),
-                            animations.remove(&name).expect(
-                                "images starting with anim_ should only be made by this tool",
-                            ),
+                            frames,
+                            meta,
                         ),
                     );
                 } else if name.starts_with("sheet_") {
@@ -428,10 +529,14 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
                         ),
                     );
                 } else if name.starts_with("normal_pair_") {
+                    let pair_name = name.strip_prefix("normal_pair_").unwrap();
                     regions.insert(
-                        name.strip_prefix("normal_pair_").unwrap().to_string(),
+                        pair_name.to_string(),
                         Region::NormalPair(
-                            Rect::new(strip.used_width, strip.y, image.width(), image.height() / 2),
+                            sprite_meta.sprite(
+                                pair_name,
+                                Rect::new(strip.used_width, strip.y, image.width(), image.height() / 2),
+                            )?,
                             Rect::new(
                                 strip.used_width,
                                 strip.y + image.height() / 2,
@@ -443,12 +548,10 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
                 } else {
                     regions.insert(
                         name.clone(),
-                        Region::Single(Rect::new(
-                            strip.used_width,
-                            strip.y,
-                            image.width(),
-                            image.height(),
-                        )),
+                        Region::Single(sprite_meta.sprite(
+                            &name,
+                            Rect::new(strip.used_width, strip.y, image.width(), image.height()),
+                        )?),
                     );
                 }
                 atlas.copy_from(&image, strip.used_width, strip.y)?;
@@ -456,11 +559,11 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
                 Ok(())
             } else {
                 expand_region(atlas);
-                add_image(name, image, atlas, regions, strips, atlases, animations)
+                add_image(name, image, atlas, regions, strips, atlases, animations, sprite_meta)
             }
         } else {
             expand_region(atlas);
-            add_image(name, image, atlas, regions, strips, atlases, animations)
+            add_image(name, image, atlas, regions, strips, atlases, animations, sprite_meta)
         }
     }
     let mut atlas: RgbaImage = ImageBuffer::new(
@@ -483,6 +586,7 @@ fn create_atlas<P1: AsRef<Path>, P2: AsRef<Path>>(
             &mut strips,
             &mut atlases,
             &mut animations,
+            &sprite_meta,
         )?;
     }
 
@@ -555,9 +659,71 @@ fn expand_region(region: &mut RgbaImage) -> ExpandDirection {
         ExpandDirection::Width
     }
 }
-fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Error>> {
+
+// animation frames can be normal pairs too, with the albedo in the top half of rect
+fn frame_region(name: &str, rect: Rect, sprite_meta: &SpriteMeta) -> Result<Region, Box<dyn Error>> {
+    let Some(pair_name) = name.strip_prefix("normal_pair_") else {
+        return Ok(Region::Single(sprite_meta.sprite(name, rect)?));
+    };
+    let albedo = Rect::new(rect.x, rect.y, rect.width, rect.height / 2);
+    let normal = Rect::new(rect.x, rect.y + rect.height / 2, rect.width, rect.height / 2);
+    Ok(Region::NormalPair(sprite_meta.sprite(pair_name, albedo)?, normal))
+}
+
+// saves every image that has a matching _normal image as a normal_pair_ image, with the albedo on top of the normal map,
+// and returns the pairs so the originals can be left out
+fn create_normal_pairs<P: AsRef<Path>>(folder: P) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+    let normal_pairs =
+        glob::glob(format!("{}/*.png", folder.as_ref().display()).as_str())?
+            .filter_map(|e| e.ok())
+            .filter_map(|path| {
+                let mut normal_path = path.clone();
+                normal_path.set_file_name(
+                    path.file_stem().unwrap().to_string_lossy().to_string() + "_normal.png",
+                );
+                if normal_path.exists() {
+                    Some((path, normal_path))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+    for (name, color, normal) in normal_pairs.iter().filter_map(|paths| {
+        if let (Ok(color), Ok(normal)) = (image::open(&paths.0), image::open(&paths.1)) {
+            Some((
+                paths
+                    .0
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_str()
+                    .expect("use utf file names")
+                    .to_string(),
+                color,
+                normal,
+            ))
+        } else {
+            None
+        }
+    }) {
+        if color.dimensions() != normal.dimensions() {
+            return Err("Normal map dimensions should match its pair's dimensions!".into());
+        }
+        let mut buffer = ImageBuffer::new(color.width(), color.height() * 2);
+        buffer.copy_from(&color, 0, 0)?;
+        buffer.copy_from(&normal, 0, color.height())?;
+        buffer.save(
+            PathBuf::from(folder.as_ref())
+                .join(String::from("normal_pair_") + &name + ".png"),
+        )?;
+    }
+
+    Ok(normal_pairs)
+}
+
+fn create_animation<P: AsRef<Path>>(folder: P) -> Result<(Vec<Region>, AnimationMeta), Box<dyn Error>> {
     let subfolders = get_sub_directories(&folder)?;
-    let mut animations = HashMap::<String, Vec<Region>>::new();
+    let mut animations = HashMap::<String, (Vec<Region>, AnimationMeta)>::new();
     let mut atlases = HashMap::<String, HashMap<String, Region>>::new();
     for folder in subfolders {
         if folder
@@ -567,13 +733,14 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
             .expect("use utf file names")
             .contains("anim")
         {
+            // keyed by the name of the image create_animation saves, so add_image can find it again
             animations.insert(
-                folder
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_str()
-                    .expect("use utf file names")
-                    .to_string(),
+                String::from("anim_")
+                    + folder
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_str()
+                        .expect("use utf file names"),
                 create_animation(&folder)?,
             );
         } else {
@@ -589,8 +756,10 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
         }
     }
 
+    let normal_pairs = create_normal_pairs(&folder)?;
     let mut images = glob::glob(format!("{}/*.png", folder.as_ref().display()).as_str())?
         .filter_map(|e| e.ok())
+        .filter(|x| !normal_pairs.iter().any(|pair| *x == pair.0 || *x == pair.1))
         .filter_map(|file: PathBuf| {
             if let Ok(img) = image::open(&file) {
                 Some((
@@ -608,8 +777,10 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
         .collect::<Vec<_>>();
 
     images.sort_by_key(|img| (img.1.height(), img.1.width()));
+    let sprite_meta = SpriteMeta::load(&folder)?;
 
     let mut named_regions = Vec::<(String, Region)>::new();
+    #[allow(clippy::too_many_arguments)]
     fn add_image(
         name: String,
         image: DynamicImage,
@@ -617,7 +788,8 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
         regions: &mut Vec<(String, Region)>,
         strips: &mut Vec<Strip>,
         atlases: &mut HashMap<String, HashMap<String, Region>>,
-        animations: &mut HashMap<String, Vec<Region>>,
+        animations: &mut HashMap<String, (Vec<Region>, AnimationMeta)>,
+        sprite_meta: &SpriteMeta,
     ) -> Result<(), Box<dyn Error>> {
         for strip in strips.iter_mut() {
             if strip.height < image.height() {
@@ -625,13 +797,15 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
             }
             if strip.used_width + image.width() <= animation.width() {
                 if name.contains("anim") {
+                    let (frames, meta) = animations
+                        .remove(&name)
+                        .expect("images containing anim should only be made by this tool");
                     regions.push((
                         name.clone(),
                         Region::Animation(
                             Rect::new(strip.used_width, strip.y, image.width(), image.height()),
-                            animations
-                                .remove(&name)
-                                .expect("images containing anim should only be made by this tool"),
+                            frames,
+                            meta,
                         ),
                     ));
                 } else if name.contains("sheet") {
@@ -647,12 +821,7 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
                 } else {
                     regions.push((
                         name.clone(),
-                        Region::Single(Rect::new(
-                            strip.used_width,
-                            strip.y,
-                            image.width(),
-                            image.height(),
-                        )),
+                        frame_region(&name, Rect::new(strip.used_width, strip.y, image.width(), image.height()), sprite_meta)?,
                     ));
                 }
                 animation.copy_from(&image, strip.used_width, strip.y)?;
@@ -677,13 +846,15 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
             let strip = add_strip(strips, &image);
             if strip.used_width + image.width() <= animation.width() {
                 if name.contains("anim") {
+                    let (frames, meta) = animations
+                        .remove(&name)
+                        .expect("images containing anim should only be made by this tool");
                     regions.push((
                         name.clone(),
                         Region::Animation(
                             Rect::new(strip.used_width, strip.y, image.width(), image.height()),
-                            animations
-                                .remove(&name)
-                                .expect("images containing anim should only be made by this tool"),
+                            frames,
+                            meta,
                         ),
                     ));
                 } else if name.contains("sheet") {
@@ -699,12 +870,7 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
                 } else {
                     regions.push((
                         name.clone(),
-                        Region::Single(Rect::new(
-                            strip.used_width,
-                            strip.y,
-                            image.width(),
-                            image.height(),
-                        )),
+                        frame_region(&name, Rect::new(strip.used_width, strip.y, image.width(), image.height()), sprite_meta)?,
                     ));
                 }
                 animation.copy_from(&image, strip.used_width, strip.y)?;
@@ -712,11 +878,11 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
                 Ok(())
             } else {
                 expand_region(animation);
-                add_image(name, image, animation, regions, strips, atlases, animations)
+                add_image(name, image, animation, regions, strips, atlases, animations, sprite_meta)
             }
         } else {
             expand_region(animation);
-            add_image(name, image, animation, regions, strips, atlases, animations)
+            add_image(name, image, animation, regions, strips, atlases, animations, sprite_meta)
         }
     }
     let mut animation: RgbaImage = ImageBuffer::new(
@@ -739,6 +905,7 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
             &mut strips,
             &mut atlases,
             &mut animations,
+            &sprite_meta,
         )?;
     }
 
@@ -764,6 +931,7 @@ fn create_animation<P: AsRef<Path>>(folder: P) -> Result<Vec<Region>, Box<dyn Er
             .parse::<u32>()
             .expect("animation frames must be numbered")
     });
-    let (_names, regions): (Vec<String>, _) = named_regions.into_iter().unzip();
-    Ok(regions)
+    let (_names, regions): (Vec<String>, Vec<Region>) = named_regions.into_iter().unzip();
+    let meta = AnimationMeta::load(&folder, regions.len())?;
+    Ok((regions, meta))
 }